@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::sync::watch;
+
+/// 用于管理遍历大量漫画的批量任务，同一种批量任务同时只能运行一个
+///
+/// 克隆 `BatchJobManager` 的开销极小，性能开销几乎可以忽略不计。
+/// 可以放心地在多个线程中传递和使用它的克隆副本。
+///
+/// 具体来说：
+/// - 所有字段都被 `Arc` 包裹，这些字段的克隆操作仅仅是增加引用计数。
+#[derive(Clone, Default)]
+pub struct BatchJobManager {
+    /// 正在运行的批量任务，值为该任务的取消信号
+    cancel_senders: Arc<Mutex<HashMap<BatchJobKind, watch::Sender<bool>>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+pub enum BatchJobKind {
+    DownloadAllFavorites,
+    UpdateDownloadedComics,
+    RefreshLibraryMetadata,
+    CheckLibraryUpdates,
+    DownloadComicList,
+}
+
+impl BatchJobKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            BatchJobKind::DownloadAllFavorites => "下载整个收藏夹",
+            BatchJobKind::UpdateDownloadedComics => "更新库存",
+            BatchJobKind::RefreshLibraryMetadata => "刷新库存元数据",
+            BatchJobKind::CheckLibraryUpdates => "检查更新",
+            BatchJobKind::DownloadComicList => "批量下载漫画列表",
+        }
+    }
+}
+
+impl BatchJobManager {
+    /// 登记一个批量任务，同一种批量任务正在运行时返回错误
+    ///
+    /// 返回的`BatchJob`被drop时自动注销
+    pub fn start(&self, kind: BatchJobKind) -> anyhow::Result<BatchJob> {
+        let mut cancel_senders = self.cancel_senders.lock();
+        if cancel_senders.contains_key(&kind) {
+            return Err(anyhow!(
+                "`{}`正在进行中，请等待其完成或取消后再试",
+                kind.name()
+            ));
+        }
+
+        let (cancel_sender, cancel_receiver) = watch::channel(false);
+        cancel_senders.insert(kind, cancel_sender);
+
+        Ok(BatchJob {
+            kind,
+            cancel_receiver,
+            cancel_senders: self.cancel_senders.clone(),
+        })
+    }
+
+    pub fn cancel(&self, kind: BatchJobKind) -> anyhow::Result<()> {
+        let cancel_senders = self.cancel_senders.lock();
+        let Some(cancel_sender) = cancel_senders.get(&kind) else {
+            return Err(anyhow!("`{}`没有在进行中", kind.name()));
+        };
+        cancel_sender.send_replace(true);
+        Ok(())
+    }
+}
+
+/// 正在运行的批量任务，批量任务应该在处理每个漫画前检查`is_cancelled`
+pub struct BatchJob {
+    kind: BatchJobKind,
+    cancel_receiver: watch::Receiver<bool>,
+    cancel_senders: Arc<Mutex<HashMap<BatchJobKind, watch::Sender<bool>>>>,
+}
+
+impl BatchJob {
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancel_receiver.borrow()
+    }
+
+    /// 休息`duration`，如果期间任务被取消则立即返回
+    pub async fn sleep(&self, duration: Duration) {
+        let mut cancel_receiver = self.cancel_receiver.clone();
+        tokio::select! {
+            () = tokio::time::sleep(duration) => {}
+            _ = cancel_receiver.wait_for(|is_cancelled| *is_cancelled) => {}
+        }
+    }
+}
+
+impl Drop for BatchJob {
+    fn drop(&mut self) {
+        self.cancel_senders.lock().remove(&self.kind);
+    }
+}
@@ -13,16 +13,29 @@ use tokio::task::JoinSet;
 use tokio::time::sleep;
 use walkdir::WalkDir;
 
+use crate::batch_job_manager::{BatchJob, BatchJobKind};
 use crate::config::Config;
 use crate::errors::{CommandError, CommandResult};
-use crate::events::{DownloadAllFavoritesEvent, UpdateDownloadedComicsEvent};
+use crate::events::{
+    CheckLibraryUpdatesEvent, DownloadAllFavoritesEvent, DownloadComicListEvent,
+    RefreshLibraryMetadataEvent, UpdateDownloadedComicsEvent,
+};
+use crate::export::ExportArchive;
+use crate::export_manager::{ExportJobSnapshot, ExportScope};
 use crate::extensions::{AnyhowErrorToStringChain, AppHandleExt, WalkDirEntryExt};
-use crate::responses::{GetUserProfileRespData, GetWeeklyInfoRespData};
+use crate::jm_client::JmClient;
+use crate::library::{ComicUpdateFailure, LibraryUpdateReport};
+use crate::migrations::{self, LibraryMigrationReport};
+use crate::responses::{
+    ComicInFavoriteRespData, EditFavoriteFolderRespData, FavoriteFolderRespData,
+    GetCategoriesRespData, GetUserProfileRespData, GetWeeklyInfoRespData, SearchResp,
+};
 use crate::types::{
-    ChapterInfo, Comic, ComicInFavorite, ComicInSearch, ComicInWeekly, FavoriteSort,
-    GetFavoriteResult, GetWeeklyResult, SearchResultVariant, SearchSort,
+    ChapterInfo, Comic, ComicCategory, ComicInFavorite, ComicInSearch, ComicInWeekly,
+    ComicListSource, FavoriteSort, GetCategoryFilterResult, GetFavoriteResult, GetWeeklyResult,
+    SearchMainTag, SearchResultVariant, SearchSort, SearchTime,
 };
-use crate::{export, logger, utils};
+use crate::{library, logger, utils};
 
 #[tauri::command]
 #[specta::specta]
@@ -51,6 +64,10 @@ pub fn save_config(app: AppHandle, config: Config) -> CommandResult<()> {
             || config_state.proxy_port != config.proxy_port
     };
 
+    config
+        .validate()
+        .map_err(|err| CommandError::from("保存配置失败", err))?;
+
     let enable_file_logger = config.enable_file_logger;
     let file_logger_changed = config_state.read().enable_file_logger != enable_file_logger;
 
@@ -110,6 +127,7 @@ pub async fn get_user_profile(app: AppHandle) -> CommandResult<GetUserProfileRes
     Ok(user_profile)
 }
 
+#[allow(clippy::too_many_arguments)]
 #[tauri::command]
 #[specta::specta]
 pub async fn search(
@@ -117,11 +135,14 @@ pub async fn search(
     keyword: String,
     page: i64,
     sort: SearchSort,
+    main_tag: SearchMainTag,
+    time: SearchTime,
+    category: ComicCategory,
 ) -> CommandResult<SearchResultVariant> {
     let jm_client = app.get_jm_client();
 
     let search_resp = jm_client
-        .search(&keyword, page, sort)
+        .search(&keyword, page, sort, main_tag, time, category)
         .await
         .map_err(|err| CommandError::from("搜索失败", err))?;
 
@@ -195,6 +216,65 @@ pub async fn get_weekly(
     Ok(get_weekly_result)
 }
 
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn get_categories(app: AppHandle) -> CommandResult<GetCategoriesRespData> {
+    let jm_client = app.get_jm_client();
+
+    let get_categories_resp_data = jm_client
+        .get_categories()
+        .await
+        .map_err(|err| CommandError::from("获取分类列表失败", err))?;
+
+    Ok(get_categories_resp_data)
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn get_category_filter(
+    app: AppHandle,
+    category_slug: String,
+    page: i64,
+    sort: SearchSort,
+    time: SearchTime,
+) -> CommandResult<GetCategoryFilterResult> {
+    let jm_client = app.get_jm_client();
+
+    let get_category_filter_resp_data = jm_client
+        .get_category_filter(&category_slug, page, sort, time)
+        .await
+        .map_err(|err| CommandError::from("按分类浏览失败", err))?;
+
+    let get_category_filter_result =
+        GetCategoryFilterResult::from_resp_data(&app, get_category_filter_resp_data)
+            .map_err(|err| CommandError::from("按分类浏览失败", err))?;
+
+    Ok(get_category_filter_result)
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn get_latest(app: AppHandle, page: i64) -> CommandResult<Vec<ComicInSearch>> {
+    let jm_client = app.get_jm_client();
+
+    let latest_resp_data = jm_client
+        .get_latest(page)
+        .await
+        .map_err(|err| CommandError::from("获取最新更新失败", err))?;
+
+    let id_to_dir_map = utils::create_id_to_dir_map(&app)
+        .context("创建漫画ID到下载目录映射失败")
+        .map_err(|err| CommandError::from("获取最新更新失败", err))?;
+
+    let latest = latest_resp_data
+        .into_iter()
+        .map(|comic| ComicInSearch::from_resp_data(comic, &id_to_dir_map))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map_err(|err| CommandError::from("获取最新更新失败", err))?;
+
+    Ok(latest)
+}
+
 #[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
@@ -294,55 +374,61 @@ pub async fn download_comic(app: AppHandle, aid: i64) -> CommandResult<()> {
     Ok(())
 }
 
+/// 下载`folder_ids`中的收藏夹，`folder_ids`为空时下载整个收藏夹
+///
+/// 从指定收藏夹下载的漫画会记住收藏夹名，用于`dir_fmt`中的`{favorite_folder}`
 #[allow(clippy::cast_possible_wrap)]
+#[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
-pub async fn download_all_favorites(app: AppHandle) -> CommandResult<()> {
+pub async fn download_all_favorites(app: AppHandle, folder_ids: Vec<i64>) -> CommandResult<()> {
     let config = app.get_config();
     let jm_client = app.get_jm_client().inner().clone();
     let download_manager = app.get_download_manager();
+    let batch_job = app
+        .get_batch_job_manager()
+        .start(BatchJobKind::DownloadAllFavorites)
+        .map_err(|err| CommandError::from("下载整个收藏夹失败", err))?;
 
-    let mut favorite_comics = Vec::new();
+    // 收藏夹中的漫画，以及下载时使用的收藏夹名
+    let mut favorite_comics: Vec<(ComicInFavoriteRespData, Option<String>)> = Vec::new();
     // 发送正在获取收藏夹事件
     let _ = DownloadAllFavoritesEvent::GetFavoritesStart.emit(&app);
-    // 获取收藏夹第一页
-    let first_page = jm_client
-        .get_favorite_folder(0, 1, FavoriteSort::FavoriteTime)
-        .await
-        .map_err(|err| CommandError::from("获取收藏夹失败", err))?;
-    favorite_comics.extend(first_page.list);
-    // 计算总页数
-    let count = first_page.count;
-    let total = first_page
-        .total
-        .parse::<i64>()
-        .map_err(|err| CommandError::from("获取收藏夹失败", err))?;
-    let page_count = (total / count) + 1;
-    // 获取收藏夹剩余页
-    let sem = Arc::new(Semaphore::new(5));
-    let mut join_set = JoinSet::new();
-    for page in 2..=page_count {
-        let jm_client = jm_client.clone();
-        let sem = sem.clone();
-        join_set.spawn(async move {
-            let _permit = sem.acquire().await?;
-            let page = jm_client
-                .get_favorite_folder(0, page, FavoriteSort::FavoriteTime)
-                .await?;
-            Ok::<_, anyhow::Error>(page)
-        });
+    if folder_ids.is_empty() {
+        let (comics, _) = get_all_favorite_comics(&jm_client, 0)
+            .await
+            .map_err(|err| CommandError::from("获取收藏夹失败", err))?;
+        favorite_comics.extend(comics.into_iter().map(|comic| (comic, None)));
     }
-    // 等待所有请求完成
-    while let Some(Ok(get_favorite_result)) = join_set.join_next().await {
-        // 如果有请求失败，直接返回错误
-        let page = get_favorite_result.map_err(|err| CommandError::from("获取收藏夹失败", err))?;
-        favorite_comics.extend(page.list);
+    for &folder_id in &folder_ids {
+        let (comics, folder_list) = get_all_favorite_comics(&jm_client, folder_id)
+            .await
+            .map_err(|err| CommandError::from("获取收藏夹失败", err))?;
+        let folder_name = folder_list
+            .into_iter()
+            .find(|folder| folder.fid == folder_id.to_string())
+            .map(|folder| folder.name)
+            .context(format!("未找到ID为`{folder_id}`的收藏夹"))
+            .map_err(|err| CommandError::from("获取收藏夹失败", err))?;
+        for comic in comics {
+            // 同一个漫画在多个收藏夹中时，按第一个收藏夹下载
+            if favorite_comics.iter().any(|(c, _)| c.id == comic.id) {
+                continue;
+            }
+            favorite_comics.push((comic, Some(folder_name.clone())));
+        }
     }
     // 至此，收藏夹已经全部获取完毕
     let total = favorite_comics.len() as i64;
 
     let interval_sec = config.read().download_all_favorites_interval_sec;
-    for (i, favorite_comic) in favorite_comics.into_iter().enumerate() {
+    let (mut processed_count, mut created_task_count) = (0, 0);
+    for (i, (favorite_comic, favorite_folder)) in favorite_comics.into_iter().enumerate() {
+        if batch_job.is_cancelled() {
+            break;
+        }
+        processed_count += 1;
+
         let comic_title = &favorite_comic.name;
         let comic_id = match favorite_comic
             .id
@@ -354,19 +440,19 @@ pub async fn download_all_favorites(app: AppHandle) -> CommandResult<()> {
                 let err_title = format!("下载收藏夹过程中，获取漫画`{comic_title}`失败，已跳过");
                 let string_chain = err.to_string_chain();
                 tracing::error!(err_title, message = string_chain);
-                sleep(Duration::from_secs(interval_sec)).await;
+                batch_job.sleep(Duration::from_secs(interval_sec)).await;
                 continue;
             }
         };
 
-        let comic = match utils::get_comic(app.clone(), comic_id).await {
+        let mut comic = match utils::get_comic(app.clone(), comic_id).await {
             Ok(comic) => comic,
             Err(err) => {
                 let err_title = format!("下载收藏夹过程中，获取漫画`{comic_title}`失败，已跳过");
                 let err = err.context("可能是频率太高，请手动去`配置`里调整`下载整个收藏夹时，每处理完一个收藏夹中的漫画后休息`");
                 let string_chain = err.to_string_chain();
                 tracing::error!(err_title, message = string_chain);
-                sleep(Duration::from_secs(interval_sec)).await;
+                batch_job.sleep(Duration::from_secs(interval_sec)).await;
                 continue;
             }
         };
@@ -374,6 +460,11 @@ pub async fn download_all_favorites(app: AppHandle) -> CommandResult<()> {
         let current = (i + 1) as i64;
         let _ = DownloadAllFavoritesEvent::GetComicsProgress { current, total }.emit(&app);
 
+        // 已下载的漫画沿用下载时的收藏夹，避免同一个漫画的章节分散到不同的目录
+        if comic.is_downloaded != Some(true) {
+            comic.favorite_folder = favorite_folder;
+        }
+
         // 给每个漫画未下载的章节创建下载任务
         let chapter_infos: Vec<&ChapterInfo> = comic
             .chapter_infos
@@ -382,7 +473,7 @@ pub async fn download_all_favorites(app: AppHandle) -> CommandResult<()> {
             .collect();
 
         if chapter_infos.is_empty() {
-            sleep(Duration::from_secs(interval_sec)).await;
+            batch_job.sleep(Duration::from_secs(interval_sec)).await;
             continue;
         }
 
@@ -396,7 +487,12 @@ pub async fn download_all_favorites(app: AppHandle) -> CommandResult<()> {
 
         for (current, chapter_info) in chapter_infos.into_iter().enumerate() {
             let current = current as i64 + 1;
-            let _ = download_manager.create_download_task(comic.clone(), chapter_info.chapter_id);
+            if download_manager
+                .create_download_task(comic.clone(), chapter_info.chapter_id)
+                .is_ok()
+            {
+                created_task_count += 1;
+            }
 
             let _ = DownloadAllFavoritesEvent::CreatingDownloadTask {
                 comic_id: comic.id,
@@ -409,20 +505,314 @@ pub async fn download_all_favorites(app: AppHandle) -> CommandResult<()> {
 
         let _ = DownloadAllFavoritesEvent::EndCreateDownloadTasks { comic_id: comic.id }.emit(&app);
 
-        sleep(Duration::from_secs(interval_sec)).await;
+        batch_job.sleep(Duration::from_secs(interval_sec)).await;
+    }
+    // 至此，所有收藏夹漫画的下载任务已经全部创建完毕，或者任务被取消
+    let cancelled = batch_job.is_cancelled();
+    if cancelled {
+        tracing::info!(
+            processed_count,
+            total,
+            created_task_count,
+            "下载整个收藏夹已取消"
+        );
     }
-    // 至此，所有收藏夹漫画的下载任务已经全部创建完毕
-    let _ = DownloadAllFavoritesEvent::GetComicsEnd.emit(&app);
+    let _ = DownloadAllFavoritesEvent::GetComicsEnd {
+        cancelled,
+        processed_count,
+        total,
+        created_task_count,
+    }
+    .emit(&app);
 
     Ok(())
 }
 
+/// 获取收藏夹`folder_id`中所有的漫画，`folder_id`为0表示整个收藏夹，同时返回用户的收藏夹列表
+#[allow(clippy::cast_possible_wrap)]
+async fn get_all_favorite_comics(
+    jm_client: &JmClient,
+    folder_id: i64,
+) -> anyhow::Result<(Vec<ComicInFavoriteRespData>, Vec<FavoriteFolderRespData>)> {
+    let mut favorite_comics = Vec::new();
+    // 获取收藏夹第一页
+    let first_page = jm_client
+        .get_favorite_folder(folder_id, 1, FavoriteSort::FavoriteTime)
+        .await?;
+    favorite_comics.extend(first_page.list);
+    // 计算总页数
+    let count = first_page.count;
+    let total = first_page.total.parse::<i64>()?;
+    let page_count = (total / count) + 1;
+    // 获取收藏夹剩余页
+    let sem = Arc::new(Semaphore::new(5));
+    let mut join_set = JoinSet::new();
+    for page in 2..=page_count {
+        let jm_client = jm_client.clone();
+        let sem = sem.clone();
+        join_set.spawn(async move {
+            let _permit = sem.acquire().await?;
+            let page = jm_client
+                .get_favorite_folder(folder_id, page, FavoriteSort::FavoriteTime)
+                .await?;
+            Ok::<_, anyhow::Error>(page)
+        });
+    }
+    // 等待所有请求完成
+    while let Some(Ok(get_favorite_result)) = join_set.join_next().await {
+        // 如果有请求失败，直接返回错误
+        let page = get_favorite_result?;
+        favorite_comics.extend(page.list);
+    }
+
+    Ok((favorite_comics, first_page.folder_list))
+}
+
+/// 下载`source`中所有未下载的漫画，会自动翻页，已下载的漫画会被跳过
+///
+/// `max_items`为最多下载的漫画数量，为0表示不限制
+#[allow(clippy::cast_possible_wrap)]
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn download_comic_list(
+    app: AppHandle,
+    source: ComicListSource,
+    max_items: i64,
+) -> CommandResult<()> {
+    let config = app.get_config();
+    let download_manager = app.get_download_manager();
+    let batch_job = app
+        .get_batch_job_manager()
+        .start(BatchJobKind::DownloadComicList)
+        .map_err(|err| CommandError::from("批量下载漫画列表失败", err))?;
+
+    let source_name = source.name();
+    // 发送正在获取漫画列表事件
+    let _ = DownloadComicListEvent::GetComicListStart.emit(&app);
+    let (comics, skipped_count) = get_comic_list(&app, &batch_job, &source, max_items)
+        .await
+        .map_err(|err| CommandError::from(&format!("获取{source_name}失败"), err))?;
+    // 至此，漫画列表已经全部获取完毕
+    let total = comics.len() as i64;
+
+    let interval_sec = config.read().download_comic_list_interval_sec;
+    let (mut processed_count, mut created_task_count) = (0, 0);
+    for (i, (comic_id, comic_title)) in comics.into_iter().enumerate() {
+        if batch_job.is_cancelled() {
+            break;
+        }
+        processed_count += 1;
+
+        let comic = match utils::get_comic(app.clone(), comic_id).await {
+            Ok(comic) => comic,
+            Err(err) => {
+                let err_title =
+                    format!("下载{source_name}过程中，获取漫画`{comic_title}`失败，已跳过");
+                let err = err.context("可能是频率太高，请手动去`配置`里调整`批量下载漫画列表时，每处理完一个漫画后休息`");
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+                batch_job.sleep(Duration::from_secs(interval_sec)).await;
+                continue;
+            }
+        };
+
+        let current = (i + 1) as i64;
+        let _ = DownloadComicListEvent::GetComicsProgress { current, total }.emit(&app);
+
+        // 给每个漫画未下载的章节创建下载任务
+        let chapter_infos: Vec<&ChapterInfo> = comic
+            .chapter_infos
+            .iter()
+            .filter(|chapter_info| chapter_info.is_downloaded != Some(true))
+            .collect();
+
+        if chapter_infos.is_empty() {
+            batch_job.sleep(Duration::from_secs(interval_sec)).await;
+            continue;
+        }
+
+        let _ = DownloadComicListEvent::StartCreateDownloadTasks {
+            comic_id: comic.id,
+            comic_title: comic.name.clone(),
+            current: 0,
+            total: chapter_infos.len() as i64,
+        }
+        .emit(&app);
+
+        for (current, chapter_info) in chapter_infos.into_iter().enumerate() {
+            let current = current as i64 + 1;
+            if download_manager
+                .create_download_task(comic.clone(), chapter_info.chapter_id)
+                .is_ok()
+            {
+                created_task_count += 1;
+            }
+
+            let _ = DownloadComicListEvent::CreatingDownloadTask {
+                comic_id: comic.id,
+                current,
+            }
+            .emit(&app);
+
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        let _ = DownloadComicListEvent::EndCreateDownloadTasks { comic_id: comic.id }.emit(&app);
+
+        batch_job.sleep(Duration::from_secs(interval_sec)).await;
+    }
+    // 至此，所有漫画的下载任务已经全部创建完毕，或者任务被取消
+    let cancelled = batch_job.is_cancelled();
+    if cancelled {
+        tracing::info!(
+            processed_count,
+            total,
+            skipped_count,
+            created_task_count,
+            "批量下载{source_name}已取消"
+        );
+    }
+    let _ = DownloadComicListEvent::GetComicsEnd {
+        cancelled,
+        processed_count,
+        total,
+        skipped_count,
+        created_task_count,
+    }
+    .emit(&app);
+
+    Ok(())
+}
+
+/// 获取`source`中未下载的漫画的ID和标题，同时返回因已下载而被跳过的漫画数量
+///
+/// 未下载的漫画数量达到`max_items`后不再继续翻页，`max_items`为0表示不限制
+#[allow(clippy::cast_possible_wrap)]
+async fn get_comic_list(
+    app: &AppHandle,
+    batch_job: &BatchJob,
+    source: &ComicListSource,
+    max_items: i64,
+) -> anyhow::Result<(Vec<(i64, String)>, i64)> {
+    let jm_client = app.get_jm_client();
+    let max_items = usize::try_from(max_items).ok().filter(|&max| max > 0);
+    let reached_max = |found_count: usize| max_items.is_some_and(|max| found_count >= max);
+
+    let mut comics = Vec::new();
+    let mut skipped_count = 0;
+    match source {
+        ComicListSource::Search {
+            keyword,
+            sort,
+            main_tag,
+            time,
+            category,
+        } => {
+            // 翻页时下载目录不会变化，只需遍历一次下载目录
+            let id_to_dir_map =
+                utils::create_id_to_dir_map(app).context("创建漫画ID到下载目录映射失败")?;
+            let mut page = 1;
+            // 已经获取的搜索结果数量，包括已下载的
+            let mut fetched_count = 0;
+            while !batch_job.is_cancelled() {
+                let search_resp = jm_client
+                    .search(
+                        keyword,
+                        page,
+                        sort.clone(),
+                        main_tag.clone(),
+                        time.clone(),
+                        category.clone(),
+                    )
+                    .await
+                    .context(format!("获取第{page}页搜索结果失败"))?;
+                let search_resp_data = match search_resp {
+                    SearchResp::SearchRespData(search_resp_data) => search_resp_data,
+                    // 关键词是jm号时，搜索结果直接就是漫画
+                    SearchResp::ComicRespData(get_comic_resp) => {
+                        if id_to_dir_map.contains_key(&get_comic_resp.id) {
+                            skipped_count += 1;
+                        } else {
+                            comics.push((get_comic_resp.id, get_comic_resp.name));
+                        }
+                        break;
+                    }
+                };
+                if search_resp_data.content.is_empty() {
+                    break;
+                }
+
+                let total = search_resp_data.total;
+                let content = search_resp_data
+                    .content
+                    .into_iter()
+                    .map(|comic| ComicInSearch::from_resp_data(comic, &id_to_dir_map))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                fetched_count += content.len() as i64;
+                for comic in content {
+                    if reached_max(comics.len()) {
+                        break;
+                    }
+                    if comic.is_downloaded {
+                        skipped_count += 1;
+                        continue;
+                    }
+                    comics.push((comic.id, comic.name));
+                }
+
+                let found_count = comics.len() as i64;
+                let _ =
+                    DownloadComicListEvent::GetComicListProgress { page, found_count }.emit(app);
+
+                if reached_max(comics.len()) || fetched_count >= total {
+                    break;
+                }
+                page += 1;
+            }
+        }
+        ComicListSource::Weekly {
+            category_id,
+            type_id,
+        } => {
+            let get_weekly_resp_data = jm_client
+                .get_weekly(category_id, type_id)
+                .await
+                .context("获取每周必看失败")?;
+            let get_weekly_result = GetWeeklyResult::from_resp_data(app, get_weekly_resp_data)?;
+            for comic in get_weekly_result.list {
+                if reached_max(comics.len()) {
+                    break;
+                }
+                if comic.is_downloaded {
+                    skipped_count += 1;
+                    continue;
+                }
+                comics.push((comic.id, comic.name));
+            }
+
+            let found_count = comics.len() as i64;
+            let _ = DownloadComicListEvent::GetComicListProgress {
+                page: 1,
+                found_count,
+            }
+            .emit(app);
+        }
+    }
+
+    Ok((comics, skipped_count))
+}
+
 #[allow(clippy::cast_possible_wrap)]
 #[tauri::command(async)]
 #[specta::specta]
 pub async fn update_downloaded_comics(app: AppHandle) -> CommandResult<()> {
     let config = app.get_config();
     let download_manager = app.get_download_manager();
+    let batch_job = app
+        .get_batch_job_manager()
+        .start(BatchJobKind::UpdateDownloadedComics)
+        .map_err(|err| CommandError::from("更新库存失败", err))?;
 
     // 从下载目录中获取已下载的漫画
     let downloaded_comics = get_downloaded_comics(app.clone());
@@ -431,7 +821,13 @@ pub async fn update_downloaded_comics(app: AppHandle) -> CommandResult<()> {
     let interval_sec = config.read().update_downloaded_comics_interval_sec;
     let _ = UpdateDownloadedComicsEvent::GetComicStart { total }.emit(&app);
 
+    let (mut processed_count, mut created_task_count) = (0, 0);
     for (i, downloaded_comic) in downloaded_comics.into_iter().enumerate() {
+        if batch_job.is_cancelled() {
+            break;
+        }
+        processed_count += 1;
+
         let comic_title = &downloaded_comic.name;
         let comic_id = downloaded_comic.id;
         let current = (i + 1) as i64;
@@ -447,7 +843,7 @@ pub async fn update_downloaded_comics(app: AppHandle) -> CommandResult<()> {
                 let err = err.context("可能是频率太高，请手动去`配置`里调整`更新库存时，每处理完一个已下载的漫画后休息`");
                 let string_chain = err.to_string_chain();
                 tracing::error!(err_title, message = string_chain);
-                sleep(Duration::from_secs(interval_sec)).await;
+                batch_job.sleep(Duration::from_secs(interval_sec)).await;
                 continue;
             }
         };
@@ -459,7 +855,7 @@ pub async fn update_downloaded_comics(app: AppHandle) -> CommandResult<()> {
             .any(|chapter_info| chapter_info.is_downloaded == Some(true));
 
         if !has_downloaded_chapter {
-            sleep(Duration::from_secs(interval_sec)).await;
+            batch_job.sleep(Duration::from_secs(interval_sec)).await;
             continue;
         }
 
@@ -470,7 +866,7 @@ pub async fn update_downloaded_comics(app: AppHandle) -> CommandResult<()> {
             .collect();
 
         if chapter_infos.is_empty() {
-            sleep(Duration::from_secs(interval_sec)).await;
+            batch_job.sleep(Duration::from_secs(interval_sec)).await;
             continue;
         }
 
@@ -486,7 +882,12 @@ pub async fn update_downloaded_comics(app: AppHandle) -> CommandResult<()> {
             let chapter_id = chapter_info.chapter_id;
             let current = (i + 1) as i64;
 
-            let _ = download_manager.create_download_task(comic.clone(), chapter_id);
+            if download_manager
+                .create_download_task(comic.clone(), chapter_id)
+                .is_ok()
+            {
+                created_task_count += 1;
+            }
 
             let _ = UpdateDownloadedComicsEvent::CreateDownloadTaskProgress {
                 comic_id: comic.id,
@@ -500,14 +901,216 @@ pub async fn update_downloaded_comics(app: AppHandle) -> CommandResult<()> {
         let _ =
             UpdateDownloadedComicsEvent::CreateDownloadTasksEnd { comic_id: comic.id }.emit(&app);
 
-        sleep(Duration::from_secs(interval_sec)).await;
+        batch_job.sleep(Duration::from_secs(interval_sec)).await;
     }
 
-    let _ = UpdateDownloadedComicsEvent::GetComicEnd.emit(&app);
+    let cancelled = batch_job.is_cancelled();
+    if cancelled {
+        tracing::info!(processed_count, total, created_task_count, "更新库存已取消");
+    }
+    let _ = UpdateDownloadedComicsEvent::GetComicEnd {
+        cancelled,
+        processed_count,
+        total,
+        created_task_count,
+    }
+    .emit(&app);
 
     Ok(())
 }
 
+/// 重新获取每个已下载漫画的数据，刷新本地元数据，不下载任何章节
+///
+/// 如果`move_dirs`为`true`，还会把目录移动到按当前`dir_fmt`计算出的位置
+#[allow(clippy::cast_possible_wrap)]
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn refresh_library_metadata(app: AppHandle, move_dirs: bool) -> CommandResult<()> {
+    let batch_job = app
+        .get_batch_job_manager()
+        .start(BatchJobKind::RefreshLibraryMetadata)
+        .map_err(|err| CommandError::from("刷新库存元数据失败", err))?;
+
+    // 从下载目录中获取已下载的漫画
+    let downloaded_comics = get_downloaded_comics(app.clone());
+
+    let total = downloaded_comics.len() as i64;
+    let interval_sec = app
+        .get_config()
+        .read()
+        .update_downloaded_comics_interval_sec;
+    let _ = RefreshLibraryMetadataEvent::Start { total }.emit(&app);
+
+    let (mut refreshed_count, mut renamed_count, mut moved_count, mut failed_count) = (0, 0, 0, 0);
+    for (i, downloaded_comic) in downloaded_comics.into_iter().enumerate() {
+        if batch_job.is_cancelled() {
+            break;
+        }
+
+        let comic_title = &downloaded_comic.name;
+        let comic_id = downloaded_comic.id;
+        let current = (i + 1) as i64;
+        let _ = RefreshLibraryMetadataEvent::Progress {
+            comic_title: comic_title.clone(),
+            current,
+            total,
+        }
+        .emit(&app);
+
+        let comic = match utils::get_comic(app.clone(), comic_id)
+            .await
+            .context(format!("获取ID为`{comic_id}`的漫画失败"))
+        {
+            Ok(comic) => comic,
+            Err(err) => {
+                let err_title =
+                    format!("刷新库存元数据过程中，获取漫画`{comic_title}`失败，已跳过");
+                let err = err.context("可能是频率太高，请手动去`配置`里调整`更新库存时，每处理完一个已下载的漫画后休息`");
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+                failed_count += 1;
+                batch_job.sleep(Duration::from_secs(interval_sec)).await;
+                continue;
+            }
+        };
+
+        match library::refresh_comic_metadata(&app, &downloaded_comic, comic, move_dirs) {
+            Ok(outcome) => {
+                refreshed_count += 1;
+                renamed_count += i64::from(outcome.renamed);
+                moved_count += i64::from(outcome.moved);
+            }
+            Err(err) => {
+                let err_title = format!("刷新漫画`{comic_title}`的元数据失败，已跳过");
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+                failed_count += 1;
+            }
+        }
+
+        batch_job.sleep(Duration::from_secs(interval_sec)).await;
+    }
+
+    let cancelled = batch_job.is_cancelled();
+    tracing::info!(
+        cancelled,
+        refreshed_count,
+        renamed_count,
+        moved_count,
+        failed_count,
+        "刷新库存元数据完成"
+    );
+    let _ = RefreshLibraryMetadataEvent::End {
+        cancelled,
+        refreshed_count,
+        renamed_count,
+        moved_count,
+        failed_count,
+    }
+    .emit(&app);
+
+    Ok(())
+}
+
+/// 获取每个已下载漫画的最新数据，找出新章节和在服务器上被删除的章节，不创建任何下载任务
+///
+/// 报告会被缓存，可以通过`get_library_update_report`再次获取
+#[allow(clippy::cast_possible_wrap)]
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn check_library_updates(app: AppHandle) -> CommandResult<LibraryUpdateReport> {
+    let batch_job = app
+        .get_batch_job_manager()
+        .start(BatchJobKind::CheckLibraryUpdates)
+        .map_err(|err| CommandError::from("检查更新失败", err))?;
+
+    // 从下载目录中获取已下载的漫画
+    let downloaded_comics = get_downloaded_comics(app.clone());
+
+    let total = downloaded_comics.len() as i64;
+    let interval_sec = app
+        .get_config()
+        .read()
+        .update_downloaded_comics_interval_sec;
+    let _ = CheckLibraryUpdatesEvent::Start { total }.emit(&app);
+
+    let mut report = LibraryUpdateReport::new();
+    for (i, downloaded_comic) in downloaded_comics.into_iter().enumerate() {
+        if batch_job.is_cancelled() {
+            break;
+        }
+
+        let comic_title = &downloaded_comic.name;
+        let comic_id = downloaded_comic.id;
+        let current = (i + 1) as i64;
+        let _ = CheckLibraryUpdatesEvent::Progress {
+            comic_title: comic_title.clone(),
+            current,
+            total,
+        }
+        .emit(&app);
+
+        report.checked_count += 1;
+        match utils::get_comic(app.clone(), comic_id)
+            .await
+            .context(format!("获取ID为`{comic_id}`的漫画失败"))
+        {
+            Ok(comic) => {
+                if let Some(update) = library::diff_comic_chapters(&downloaded_comic, comic) {
+                    report.updates.push(update);
+                }
+            }
+            Err(err) => {
+                let err_title = format!("检查更新过程中，获取漫画`{comic_title}`失败，已跳过");
+                let err = err.context("可能是频率太高，请手动去`配置`里调整`更新库存时，每处理完一个已下载的漫画后休息`");
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+                report.failed.push(ComicUpdateFailure {
+                    comic_id,
+                    comic_title: comic_title.clone(),
+                    err_message: string_chain,
+                });
+            }
+        }
+
+        batch_job.sleep(Duration::from_secs(interval_sec)).await;
+    }
+
+    report.cancelled = batch_job.is_cancelled();
+    let _ = CheckLibraryUpdatesEvent::End {
+        cancelled: report.cancelled,
+        update_count: report.updates.len() as i64,
+        failed_count: report.failed.len() as i64,
+    }
+    .emit(&app);
+
+    *app.get_library_update_report().write() = Some(report.clone());
+
+    Ok(report)
+}
+
+/// 获取上一次`check_library_updates`的报告，还没有检查过时返回`None`
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn get_library_update_report(app: AppHandle) -> Option<LibraryUpdateReport> {
+    app.get_library_update_report().read().clone()
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn cancel_batch_job(app: AppHandle, kind: BatchJobKind) -> CommandResult<()> {
+    let batch_job_manager = app.get_batch_job_manager();
+
+    batch_job_manager
+        .cancel(kind)
+        .map_err(|err| CommandError::from(&format!("取消`{}`失败", kind.name()), err))?;
+
+    tracing::debug!("已请求取消`{}`", kind.name());
+    Ok(())
+}
+
 #[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
@@ -539,6 +1142,128 @@ pub async fn sync_favorite_folder(app: AppHandle) -> CommandResult<()> {
     Ok(())
 }
 
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn create_favorite_folder(
+    app: AppHandle,
+    folder_name: String,
+) -> CommandResult<EditFavoriteFolderRespData> {
+    let jm_client = app.get_jm_client();
+
+    let resp_data = jm_client
+        .create_favorite_folder(&folder_name)
+        .await
+        .map_err(|err| CommandError::from(&format!("新建收藏夹`{folder_name}`失败"), err))?;
+
+    tracing::debug!("新建收藏夹`{folder_name}`成功");
+    Ok(resp_data)
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn rename_favorite_folder(
+    app: AppHandle,
+    folder_id: i64,
+    folder_name: String,
+) -> CommandResult<EditFavoriteFolderRespData> {
+    let jm_client = app.get_jm_client();
+
+    let resp_data = jm_client
+        .rename_favorite_folder(folder_id, &folder_name)
+        .await
+        .map_err(|err| {
+            let err_title = format!("将ID为`{folder_id}`的收藏夹重命名为`{folder_name}`失败");
+            CommandError::from(&err_title, err)
+        })?;
+
+    tracing::debug!("将ID为`{folder_id}`的收藏夹重命名为`{folder_name}`成功");
+    Ok(resp_data)
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn delete_favorite_folder(
+    app: AppHandle,
+    folder_id: i64,
+) -> CommandResult<EditFavoriteFolderRespData> {
+    let jm_client = app.get_jm_client();
+
+    let resp_data = jm_client
+        .delete_favorite_folder(folder_id)
+        .await
+        .map_err(|err| CommandError::from(&format!("删除ID为`{folder_id}`的收藏夹失败"), err))?;
+
+    tracing::debug!("删除ID为`{folder_id}`的收藏夹成功");
+    Ok(resp_data)
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn move_favorite_comic(
+    app: AppHandle,
+    aid: i64,
+    folder_id: i64,
+) -> CommandResult<EditFavoriteFolderRespData> {
+    let jm_client = app.get_jm_client();
+
+    let resp_data = jm_client
+        .move_favorite_comic(aid, folder_id)
+        .await
+        .map_err(|err| {
+            let err_title = format!("将漫画`{aid}`移动到ID为`{folder_id}`的收藏夹失败");
+            CommandError::from(&err_title, err)
+        })?;
+
+    tracing::debug!("将漫画`{aid}`移动到ID为`{folder_id}`的收藏夹成功");
+    Ok(resp_data)
+}
+
+/// 收藏漫画，`folder_id`不为`None`时再移动到该收藏夹
+///
+/// 返回漫画原本是否不在收藏夹中
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn add_favorite_comic(
+    app: AppHandle,
+    aid: i64,
+    folder_id: Option<i64>,
+) -> CommandResult<bool> {
+    let jm_client = app.get_jm_client();
+
+    let added = jm_client
+        .add_favorite_comic(aid)
+        .await
+        .map_err(|err| CommandError::from(&format!("收藏漫画`{aid}`失败"), err))?;
+
+    if let Some(folder_id) = folder_id {
+        jm_client
+            .move_favorite_comic(aid, folder_id)
+            .await
+            .map_err(|err| {
+                let err_title = format!("将漫画`{aid}`移动到ID为`{folder_id}`的收藏夹失败");
+                CommandError::from(&err_title, err)
+            })?;
+    }
+
+    tracing::debug!("收藏漫画`{aid}`成功");
+    Ok(added)
+}
+
+/// 取消收藏漫画，返回漫画原本是否在收藏夹中
+#[tauri::command(async)]
+#[specta::specta]
+pub async fn remove_favorite_comic(app: AppHandle, aid: i64) -> CommandResult<bool> {
+    let jm_client = app.get_jm_client();
+
+    let removed = jm_client
+        .remove_favorite_comic(aid)
+        .await
+        .map_err(|err| CommandError::from(&format!("取消收藏漫画`{aid}`失败"), err))?;
+
+    tracing::debug!("取消收藏漫画`{aid}`成功");
+    Ok(removed)
+}
+
 #[allow(clippy::needless_pass_by_value)]
 #[allow(clippy::too_many_lines)]
 #[tauri::command(async)]
@@ -657,25 +1382,80 @@ pub fn get_downloaded_comics(app: AppHandle) -> Vec<Comic> {
 #[tauri::command(async)]
 #[specta::specta]
 #[allow(clippy::needless_pass_by_value)]
-pub fn export_cbz(app: AppHandle, comic: Comic) -> CommandResult<()> {
-    let comic_title = &comic.name;
-    export::cbz(&app, &comic)
-        .context(format!("漫画`{comic_title}`导出cbz失败"))
-        .map_err(|err| CommandError::from("导出cbz失败", err))?;
+pub fn export_cbz(app: AppHandle, comic: Comic) -> String {
+    let export_manager = app.get_export_manager();
+    let comic_title = comic.name.clone();
+
+    let job_id =
+        export_manager.create_export_job(comic, ExportArchive::Cbz, ExportScope::All, false);
+
+    tracing::debug!("创建漫画`{comic_title}`的cbz导出任务成功");
+    job_id
+}
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn export_pdf(app: AppHandle, comic: Comic) -> String {
+    let export_manager = app.get_export_manager();
+    let comic_title = comic.name.clone();
+
+    let job_id =
+        export_manager.create_export_job(comic, ExportArchive::Pdf, ExportScope::All, false);
+
+    tracing::debug!("创建漫画`{comic_title}`的pdf导出任务成功");
+    job_id
+}
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn pause_export_job(app: AppHandle, job_id: String) -> CommandResult<()> {
+    let export_manager = app.get_export_manager();
+
+    export_manager
+        .pause_export_job(&job_id)
+        .map_err(|err| CommandError::from(&format!("暂停ID为`{job_id}`的导出任务失败"), err))?;
+
+    tracing::debug!("暂停ID为`{job_id}`的导出任务成功");
     Ok(())
 }
 
+#[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
+pub fn resume_export_job(app: AppHandle, job_id: String) -> CommandResult<()> {
+    let export_manager = app.get_export_manager();
+
+    export_manager
+        .resume_export_job(&job_id)
+        .map_err(|err| CommandError::from(&format!("恢复ID为`{job_id}`的导出任务失败"), err))?;
+
+    tracing::debug!("恢复ID为`{job_id}`的导出任务成功");
+    Ok(())
+}
+
 #[allow(clippy::needless_pass_by_value)]
-pub fn export_pdf(app: AppHandle, comic: Comic) -> CommandResult<()> {
-    let comic_title = &comic.name;
-    export::pdf(&app, &comic)
-        .context(format!("漫画`{comic_title}`导出pdf失败"))
-        .map_err(|err| CommandError::from("导出pdf失败", err))?;
+#[tauri::command(async)]
+#[specta::specta]
+pub fn cancel_export_job(app: AppHandle, job_id: String) -> CommandResult<()> {
+    let export_manager = app.get_export_manager();
+
+    export_manager
+        .cancel_export_job(&job_id)
+        .map_err(|err| CommandError::from(&format!("取消ID为`{job_id}`的导出任务失败"), err))?;
+
+    tracing::debug!("取消ID为`{job_id}`的导出任务成功");
     Ok(())
 }
 
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn get_export_jobs(app: AppHandle) -> Vec<ExportJobSnapshot> {
+    app.get_export_manager().get_export_jobs()
+}
+
 #[allow(clippy::needless_pass_by_value)]
 #[tauri::command(async)]
 #[specta::specta]
@@ -767,3 +1547,18 @@ pub fn get_synced_comic_in_weekly(
 
     Ok(comic)
 }
+
+#[allow(clippy::needless_pass_by_value)]
+#[tauri::command(async)]
+#[specta::specta]
+pub fn migrate_library(app: AppHandle, dry_run: bool) -> LibraryMigrationReport {
+    let report = migrations::migrate_library(&app, dry_run);
+    tracing::debug!(
+        dry_run,
+        checked_count = report.checked_count,
+        migrated_count = report.migrated.len(),
+        failed_count = report.failed.len(),
+        "迁移库存元数据完成"
+    );
+    report
+}
@@ -1,6 +1,10 @@
 use std::path::{Path, PathBuf};
 
-use crate::types::{DownloadFormat, ProxyMode};
+use crate::export::{self, ExportArchive};
+use crate::types::{
+    DownloadFormat, PdfImageEncoding, PdfPageSize, PngCompression, ProxyMode, StorageMode,
+};
+use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tauri::{AppHandle, Manager};
@@ -19,7 +23,16 @@ pub struct Config {
     pub download_dir: PathBuf,
     pub export_dir: PathBuf,
     pub download_format: DownloadFormat,
+    pub jpeg_quality: u8,
+    pub png_compression: PngCompression,
+    pub webp_lossless: bool,
+    pub webp_quality: u8,
+    pub avif_quality: u8,
+    pub avif_speed: u8,
+    pub storage_mode: StorageMode,
     pub dir_fmt: String,
+    pub export_chapter_fmt: String,
+    pub export_comic_fmt: String,
     pub proxy_mode: ProxyMode,
     pub proxy_host: String,
     pub proxy_port: u16,
@@ -28,11 +41,29 @@ pub struct Config {
     pub chapter_download_interval_sec: u64,
     pub img_concurrency: usize,
     pub img_download_interval_sec: u64,
+    pub img_process_thread_count: usize,
+    pub img_process_queue_size: usize,
+    pub min_free_space_mb: u64,
+    pub detect_block_num: bool,
+    pub unscramble_gif: bool,
+    pub convert_gif_to_webp: bool,
     pub download_all_favorites_interval_sec: u64,
     pub update_downloaded_comics_interval_sec: u64,
+    pub download_comic_list_interval_sec: u64,
     pub api_domain_mode: ApiDomainMode,
     pub custom_api_domain: String,
     pub should_download_cover: bool,
+    pub export_job_concurrency: usize,
+    pub export_thread_count: usize,
+    pub enable_auto_export: bool,
+    pub auto_export_archive: ExportArchive,
+    pub delete_images_after_auto_export: bool,
+    pub pdf_page_size: PdfPageSize,
+    pub pdf_custom_page_width_mm: f32,
+    pub pdf_custom_page_height_mm: f32,
+    pub pdf_cover_first: bool,
+    pub pdf_image_encoding: PdfImageEncoding,
+    pub pdf_jpeg_quality: u8,
 }
 
 impl Config {
@@ -64,6 +95,33 @@ impl Config {
         Ok(())
     }
 
+    /// 检查配置中互相冲突或会导致导出结果被覆盖的选项
+    pub fn validate(&self) -> anyhow::Result<()> {
+        export::validate_export_fmts(&self.export_chapter_fmt, &self.export_comic_fmt)?;
+        // 以cbz形式保存的章节，图片就是章节本身，不能在导出后删除
+        if self.storage_mode == StorageMode::Cbz && self.delete_images_after_auto_export {
+            return Err(anyhow!("以cbz形式保存章节时，不支持自动导出后删除图片"));
+        }
+        // 导出PDF时无法解码AVIF
+        let auto_export_pdf =
+            self.enable_auto_export && self.auto_export_archive == ExportArchive::Pdf;
+        if self.download_format == DownloadFormat::Avif && auto_export_pdf {
+            return Err(anyhow!("下载格式为AVIF时，不支持自动导出为PDF"));
+        }
+        // 自定义页面尺寸用于计算图片的缩放比例，必须是正数
+        let custom_page_size = [
+            self.pdf_custom_page_width_mm,
+            self.pdf_custom_page_height_mm,
+        ];
+        if custom_page_size
+            .iter()
+            .any(|mm| !mm.is_finite() || *mm <= 0.0)
+        {
+            return Err(anyhow!("PDF自定义页面的宽和高必须大于0"));
+        }
+        Ok(())
+    }
+
     pub fn get_api_domain(&self) -> String {
         match self.api_domain_mode {
             ApiDomainMode::Domain1 => API_DOMAIN_1.to_string(),
@@ -82,6 +140,18 @@ impl Config {
         let serde_json::Value::Object(ref mut map) = json_value else {
             return Config::default(app_data_dir);
         };
+        // 旧版本没有导出路径格式，导出路径跟随下载目录格式
+        // 从`dir_fmt`推导出等价的导出路径格式，以免已有用户升级后导出路径发生变化
+        let legacy_export_fmts = map
+            .get("dirFmt")
+            .and_then(serde_json::Value::as_str)
+            .and_then(legacy_export_fmts);
+        if let Some((export_chapter_fmt, export_comic_fmt)) = legacy_export_fmts {
+            map.entry("exportChapterFmt")
+                .or_insert(export_chapter_fmt.into());
+            map.entry("exportComicFmt")
+                .or_insert(export_comic_fmt.into());
+        }
         let Ok(default_config_value) = serde_json::to_value(Config::default(app_data_dir)) else {
             return Config::default(app_data_dir);
         };
@@ -104,7 +174,16 @@ impl Config {
             download_dir: app_data_dir.join("漫画下载"),
             export_dir: app_data_dir.join("漫画导出"),
             download_format: DownloadFormat::default(),
+            jpeg_quality: 75,
+            png_compression: PngCompression::default(),
+            webp_lossless: true,
+            webp_quality: 80,
+            avif_quality: 80,
+            avif_speed: 4,
+            storage_mode: StorageMode::default(),
             dir_fmt: "{comic_title}/{chapter_title}".to_string(),
+            export_chapter_fmt: "{comic_title}/{format}/{chapter_title}".to_string(),
+            export_comic_fmt: "{comic_title}/{comic_title}".to_string(),
             proxy_mode: ProxyMode::default(),
             proxy_host: "127.0.0.1".to_string(),
             proxy_port: 7890,
@@ -113,15 +192,47 @@ impl Config {
             chapter_download_interval_sec: 0,
             img_concurrency: 20,
             img_download_interval_sec: 0,
+            img_process_thread_count: std::thread::available_parallelism()
+                .map_or(4, std::num::NonZeroUsize::get),
+            img_process_queue_size: 16,
+            min_free_space_mb: 1024,
+            detect_block_num: true,
+            unscramble_gif: true,
+            convert_gif_to_webp: false,
             download_all_favorites_interval_sec: 0,
             update_downloaded_comics_interval_sec: 0,
+            download_comic_list_interval_sec: 0,
             api_domain_mode: ApiDomainMode::Domain2,
             custom_api_domain: API_DOMAIN_2.to_string(),
             should_download_cover: true,
+            export_job_concurrency: 1,
+            // 默认只用一半的CPU核心导出，避免导出时整个系统卡顿
+            export_thread_count: std::thread::available_parallelism()
+                .map_or(2, |n| (n.get() / 2).max(1)),
+            enable_auto_export: false,
+            auto_export_archive: ExportArchive::Cbz,
+            delete_images_after_auto_export: false,
+            pdf_page_size: PdfPageSize::default(),
+            pdf_custom_page_width_mm: 210.0,
+            pdf_custom_page_height_mm: 297.0,
+            pdf_cover_first: false,
+            pdf_image_encoding: PdfImageEncoding::default(),
+            pdf_jpeg_quality: 90,
         }
     }
 }
 
+/// 旧版本把章节导出到`导出目录/漫画下载目录的相对路径/格式/章节下载目录名`，
+/// 把合并后的PDF导出到`导出目录/漫画下载目录的相对路径/漫画下载目录名`，
+/// 返回用`dir_fmt`表示的这两个路径格式
+fn legacy_export_fmts(dir_fmt: &str) -> Option<(String, String)> {
+    let (comic_dir_fmt, chapter_dir_name_fmt) = dir_fmt.rsplit_once('/')?;
+    let comic_dir_name_fmt = comic_dir_fmt.rsplit('/').next()?;
+    let export_chapter_fmt = format!("{comic_dir_fmt}/{{format}}/{chapter_dir_name_fmt}");
+    let export_comic_fmt = format!("{comic_dir_fmt}/{comic_dir_name_fmt}");
+    Some((export_chapter_fmt, export_comic_fmt))
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 pub enum ApiDomainMode {
     Domain1,
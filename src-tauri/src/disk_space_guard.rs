@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use parking_lot::Mutex;
+use tauri::AppHandle;
+use tauri_specta::Event;
+use tokio::sync::watch;
+
+use crate::events::DiskSpaceEvent;
+use crate::extensions::{AnyhowErrorToStringChain, AppHandleExt};
+
+/// 定期检查剩余空间的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 用于在下载目录或导出目录所在的磁盘空间不足时暂停所有任务，空间恢复后再继续
+///
+/// 克隆 `DiskSpaceGuard` 的开销极小，性能开销几乎可以忽略不计。
+/// 可以放心地在多个线程中传递和使用它的克隆副本。
+///
+/// 具体来说：
+/// - `app`的克隆开销很小。
+/// - 其他字段都被 `Arc` 包裹，这些字段的克隆操作仅仅是增加引用计数。
+#[derive(Clone)]
+pub struct DiskSpaceGuard {
+    app: AppHandle,
+    /// 空间不足时为`Some`，记录因空间不足而被暂停的任务，空间恢复后只继续这些任务
+    paused_tasks: Arc<Mutex<Option<PausedTasks>>>,
+    /// 空间是否不足
+    low_space_sender: Arc<watch::Sender<bool>>,
+}
+
+#[derive(Default)]
+struct PausedTasks {
+    chapter_ids: Vec<i64>,
+    export_job_ids: Vec<String>,
+}
+
+/// 剩余空间低于阈值的目录
+struct LowSpace {
+    path: PathBuf,
+    available_mb: u64,
+    min_free_space_mb: u64,
+}
+
+impl DiskSpaceGuard {
+    pub fn new(app: AppHandle) -> Self {
+        let (low_space_sender, _) = watch::channel(false);
+
+        let guard = DiskSpaceGuard {
+            app,
+            paused_tasks: Arc::new(Mutex::new(None)),
+            low_space_sender: Arc::new(low_space_sender),
+        };
+
+        tauri::async_runtime::spawn(guard.clone().check_loop());
+
+        guard
+    }
+
+    /// 开始下载章节前调用，如果空间不足，则暂停所有任务并等待空间恢复
+    pub async fn wait_for_enough_space(&self) {
+        self.check();
+
+        let mut low_space_receiver = self.low_space_sender.subscribe();
+        let _ = low_space_receiver.wait_for(|is_low| !is_low).await;
+    }
+
+    async fn check_loop(self) {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.check();
+        }
+    }
+
+    /// 检查剩余空间，空间不足时暂停所有正在进行的任务，空间恢复后继续因空间不足而被暂停的任务
+    ///
+    /// 导出任务在处理每个章节前调用，被暂停后会在`ExportJob::checkpoint`中等待恢复
+    pub fn check(&self) {
+        let low_space = match self.find_low_space() {
+            Ok(low_space) => low_space,
+            Err(err) => {
+                let err_title = "检查磁盘剩余空间失败";
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+                return;
+            }
+        };
+
+        let mut paused_tasks = self.paused_tasks.lock();
+        let was_low = paused_tasks.is_some();
+        match low_space {
+            // 空间不足，暂停所有正在进行的任务，包括空间不足期间新创建的任务
+            Some(low_space) => {
+                let paused = paused_tasks.get_or_insert_with(PausedTasks::default);
+                let chapter_ids = self.app.get_download_manager().pause_active_tasks();
+                let export_job_ids = self.app.get_export_manager().pause_active_jobs();
+                paused.chapter_ids.extend(chapter_ids);
+                paused.export_job_ids.extend(export_job_ids);
+
+                if was_low {
+                    return;
+                }
+                let LowSpace {
+                    path,
+                    available_mb,
+                    min_free_space_mb,
+                } = low_space;
+                tracing::warn!(
+                    "`{}`所在磁盘剩余空间为{available_mb}MB，低于{min_free_space_mb}MB，已暂停所有任务",
+                    path.display()
+                );
+                self.low_space_sender.send_replace(true);
+                let _ = DiskSpaceEvent::Low {
+                    path,
+                    available_mb,
+                    min_free_space_mb,
+                }
+                .emit(&self.app);
+            }
+            // 空间已恢复，继续因空间不足而被暂停的任务
+            None => {
+                let Some(paused) = paused_tasks.take() else {
+                    return;
+                };
+                self.app
+                    .get_download_manager()
+                    .resume_paused_tasks(&paused.chapter_ids);
+                self.app
+                    .get_export_manager()
+                    .resume_paused_jobs(&paused.export_job_ids);
+
+                tracing::info!("磁盘剩余空间已恢复，继续被暂停的任务");
+                self.low_space_sender.send_replace(false);
+                let _ = DiskSpaceEvent::Recovered.emit(&self.app);
+            }
+        }
+    }
+
+    /// 依次检查下载目录和导出目录，返回第一个剩余空间低于`config.min_free_space_mb`的目录
+    fn find_low_space(&self) -> anyhow::Result<Option<LowSpace>> {
+        let (min_free_space_mb, download_dir, export_dir) = {
+            let config = self.app.get_config();
+            let config = config.read();
+            (
+                config.min_free_space_mb,
+                config.download_dir.clone(),
+                config.export_dir.clone(),
+            )
+        };
+        // 阈值为0表示不检查
+        if min_free_space_mb == 0 {
+            return Ok(None);
+        }
+
+        for dir in [download_dir, export_dir] {
+            let available_mb = available_space_mb(&dir)
+                .context(format!("获取`{}`所在磁盘的剩余空间失败", dir.display()))?;
+            if available_mb < min_free_space_mb {
+                return Ok(Some(LowSpace {
+                    path: dir,
+                    available_mb,
+                    min_free_space_mb,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// 获取`path`所在磁盘的剩余可用空间，`path`不存在时用最近的存在的祖先目录
+fn available_space_mb(path: &Path) -> anyhow::Result<u64> {
+    let existing_path = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .context(format!("`{}`及其所有祖先目录都不存在", path.display()))?;
+
+    let available_bytes = fs4::available_space(existing_path)?;
+
+    Ok(available_bytes / 1024 / 1024)
+}
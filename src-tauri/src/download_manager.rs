@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::io::Cursor;
 use std::ops::ControlFlow;
 use std::path::{Path, PathBuf};
@@ -8,10 +9,15 @@ use std::time::Duration;
 
 use anyhow::{anyhow, Context};
 use bytes::Bytes;
+use image::codecs::avif::AvifEncoder;
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::jpeg::JpegEncoder;
 use image::codecs::png;
 use image::codecs::png::PngEncoder;
-use image::{ImageFormat, RgbImage};
-use parking_lot::RwLock;
+use image::{
+    AnimationDecoder, DynamicImage, Frame, GenericImageView, ImageBuffer, ImageFormat, Pixel,
+};
+use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tauri::AppHandle;
@@ -21,12 +27,15 @@ use tokio::task::JoinSet;
 use tokio::time::sleep;
 
 use crate::events::{DownloadSleepingEvent, DownloadTaskEvent};
-use crate::extensions::{AnyhowErrorToStringChain, AppHandleExt};
-use crate::types::{ChapterInfo, Comic, DownloadFormat};
-use crate::utils::filename_filter;
+use crate::export::ExportArchive;
+use crate::export_manager::ExportScope;
+use crate::extensions::{AnyhowErrorToStringChain, AppHandleExt, PathIsImg};
+use crate::types::{ChapterInfo, Comic, PngCompression, StorageMode};
 use crate::{utils, DownloadSpeedEvent};
 
 pub const IMAGE_DOMAIN: &str = "cdn-msp2.jmapiproxy2.cc";
+/// 临时下载目录名的前缀，章节下载完成后才重命名为章节目录
+pub const TEMP_DOWNLOAD_DIR_PREFIX: &str = ".下载中-";
 
 /// 用于管理下载任务
 ///
@@ -41,6 +50,12 @@ pub struct DownloadManager {
     app: AppHandle,
     chapter_sem: Arc<Semaphore>,
     img_sem: Arc<Semaphore>,
+    /// 处理(解码、拼接、编码)图片的线程池
+    img_process_pool: Arc<rayon::ThreadPool>,
+    /// 限制等待处理和正在处理的图片数量，处理跟不上下载时，下载会等待
+    img_process_sem: Arc<Semaphore>,
+    /// 等待处理和正在处理的图片数量
+    img_process_queued: Arc<AtomicU32>,
     byte_per_sec: Arc<AtomicU64>,
     download_tasks: Arc<RwLock<HashMap<i64, DownloadTask>>>,
 }
@@ -56,24 +71,38 @@ pub enum DownloadTaskState {
 }
 
 impl DownloadManager {
-    pub fn new(app: AppHandle) -> Self {
-        let (chapter_concurrency, img_concurrency) = {
+    pub fn new(app: AppHandle) -> anyhow::Result<Self> {
+        let (chapter_concurrency, img_concurrency, process_thread_count, process_queue_size) = {
             let config = app.get_config();
             let config = config.read();
-            (config.chapter_concurrency, config.img_concurrency)
+            (
+                config.chapter_concurrency,
+                config.img_concurrency,
+                config.img_process_thread_count,
+                config.img_process_queue_size,
+            )
         };
+        // 图片处理是CPU密集型操作，用专门的线程池来限制占用的线程数，避免与导出抢占全局的rayon线程池
+        let img_process_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(process_thread_count)
+            .thread_name(|i| format!("img-process-{i}"))
+            .build()
+            .context("创建图片处理线程池失败")?;
 
         let manager = DownloadManager {
             app,
             chapter_sem: Arc::new(Semaphore::new(chapter_concurrency)),
             img_sem: Arc::new(Semaphore::new(img_concurrency)),
+            img_process_pool: Arc::new(img_process_pool),
+            img_process_sem: Arc::new(Semaphore::new(process_queue_size.max(1))),
+            img_process_queued: Arc::new(AtomicU32::new(0)),
             byte_per_sec: Arc::new(AtomicU64::new(0)),
             download_tasks: Arc::new(RwLock::new(HashMap::new())),
         };
 
         tauri::async_runtime::spawn(manager.clone().emit_download_speed_loop());
 
-        manager
+        Ok(manager)
     }
 
     pub fn create_download_task(&self, comic: Comic, chapter_id: i64) -> anyhow::Result<()> {
@@ -121,6 +150,43 @@ impl DownloadManager {
         Ok(())
     }
 
+    /// 漫画`comic_id`是否有`Pending`、`Downloading`或`Paused`的下载任务
+    pub fn has_active_task_for_comic(&self, comic_id: i64) -> bool {
+        use DownloadTaskState::{Downloading, Paused, Pending};
+        self.download_tasks.read().values().any(|task| {
+            let state = *task.state_sender.borrow();
+            task.comic.id == comic_id && matches!(state, Pending | Downloading | Paused)
+        })
+    }
+
+    /// 暂停所有`Pending`或`Downloading`的下载任务，返回被暂停的任务的章节ID
+    pub fn pause_active_tasks(&self) -> Vec<i64> {
+        use DownloadTaskState::{Downloading, Paused, Pending};
+        let tasks = self.download_tasks.read();
+        let mut paused_chapter_ids = Vec::new();
+        for (chapter_id, task) in tasks.iter() {
+            let state = *task.state_sender.borrow();
+            if matches!(state, Pending | Downloading) {
+                task.set_state(Paused);
+                paused_chapter_ids.push(*chapter_id);
+            }
+        }
+        paused_chapter_ids
+    }
+
+    /// 继续`chapter_ids`中仍处于`Paused`状态的下载任务
+    pub fn resume_paused_tasks(&self, chapter_ids: &[i64]) {
+        let tasks = self.download_tasks.read();
+        for chapter_id in chapter_ids {
+            let Some(task) = tasks.get(chapter_id) else {
+                continue;
+            };
+            if *task.state_sender.borrow() == DownloadTaskState::Paused {
+                task.set_state(DownloadTaskState::Pending);
+            }
+        }
+    }
+
     #[allow(clippy::cast_precision_loss)]
     async fn emit_download_speed_loop(self) {
         let mut interval = tokio::time::interval(Duration::from_secs(1));
@@ -130,8 +196,13 @@ impl DownloadManager {
             let byte_per_sec = self.byte_per_sec.swap(0, Ordering::Relaxed);
             let mega_byte_per_sec = byte_per_sec as f64 / 1024.0 / 1024.0;
             let speed = format!("{mega_byte_per_sec:.2}MB/s");
+            let queued_img_count = self.img_process_queued.load(Ordering::Relaxed);
             // 发送总进度条下载速度事件
-            let _ = DownloadSpeedEvent { speed }.emit(&self.app);
+            let _ = DownloadSpeedEvent {
+                speed,
+                queued_img_count,
+            }
+            .emit(&self.app);
         }
     }
 }
@@ -145,6 +216,24 @@ struct DownloadTask {
     state_sender: watch::Sender<DownloadTaskState>,
     downloaded_img_count: Arc<AtomicU32>,
     total_img_count: Arc<AtomicU32>,
+    page_records: Arc<Mutex<PageRecords>>,
+}
+
+/// 下载过程中记录的每张图片的信息，会保存到临时下载目录的章节元数据中，用于恢复下载
+#[derive(Debug, Default, Clone)]
+struct PageRecords {
+    /// 自动检测出的与计算结果不一致的分块数，key为图片的序号
+    block_num_overrides: BTreeMap<String, u32>,
+    /// 原图是GIF的图片保存的扩展名，key为图片的序号
+    gif_page_exts: BTreeMap<String, String>,
+}
+
+impl PageRecords {
+    /// 忘记序号为`index_filename`的图片的记录，重新下载时会重新记录
+    fn remove(&mut self, index_filename: &str) {
+        self.block_num_overrides.remove(index_filename);
+        self.gif_page_exts.remove(index_filename);
+    }
 }
 
 impl DownloadTask {
@@ -171,6 +260,7 @@ impl DownloadTask {
             state_sender,
             downloaded_img_count: Arc::new(AtomicU32::new(0)),
             total_img_count: Arc::new(AtomicU32::new(0)),
+            page_records: Arc::new(Mutex::new(PageRecords::default())),
         };
 
         Ok(task)
@@ -211,6 +301,12 @@ impl DownloadTask {
         let chapter_title = &self.chapter_info.chapter_title;
         let chapter_id = self.chapter_info.chapter_id;
 
+        // 磁盘空间不足时所有任务都会被暂停，等空间恢复后再开始下载
+        self.app
+            .get_disk_space_guard()
+            .wait_for_enough_space()
+            .await;
+
         if let Err(err) = self.comic.save_comic_metadata() {
             let err_title = format!("`{comic_title}`保存元数据失败");
             let string_chain = err.to_string_chain();
@@ -248,8 +344,15 @@ impl DownloadTask {
         let Some(temp_download_dir) = self.create_temp_download_dir() else {
             return;
         };
+        // 恢复之前下载时记录的图片信息，否则已下载的图片会被跳过，它们的分块数就丢失了
+        self.load_page_records(&temp_download_dir);
         // 清理临时下载目录中与`config.download_format`对不上的文件
         self.clean_temp_download_dir(&temp_download_dir);
+        // 开始下载前先在临时下载目录中保存章节元数据，之后记录了新的图片信息时再更新
+        {
+            let page_records = self.page_records.lock();
+            self.save_temp_chapter_metadata(&temp_download_dir, &page_records);
+        }
 
         let mut join_set = JoinSet::new();
         for (i, (url, block_num)) in urls_with_block_num.into_iter().enumerate() {
@@ -288,17 +391,85 @@ impl DownloadTask {
             return;
         }
 
-        if let Err(err) = self.chapter_info.save_chapter_metadata() {
+        // 把自动检测出的分块数和GIF保存的扩展名记录到章节元数据中
+        let page_records = std::mem::take(&mut *self.page_records.lock());
+        let mut chapter_info = self.chapter_info.as_ref().clone();
+        chapter_info.block_num_overrides = page_records.block_num_overrides;
+        chapter_info.gif_page_exts = page_records.gif_page_exts;
+        if let Err(err) = chapter_info.save_chapter_metadata() {
             let err_title = format!("`{comic_title} - {chapter_title}`保存元数据失败");
             let string_chain = err.to_string_chain();
             tracing::error!(err_title, message = string_chain);
         }
 
+        let storage_mode = self.app.get_config().read().storage_mode;
+        if storage_mode == StorageMode::Cbz {
+            // 打包失败时章节目录仍然完整，所以只记录错误，不影响下载结果
+            if let Err(err) = self.chapter_info.pack_into_archive(&self.comic) {
+                let err_title = format!("`{comic_title} - {chapter_title}`打包为cbz失败");
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+            }
+        }
+
         self.sleep_between_chapter().await;
         tracing::info!(comic_title, chapter_title, "章节下载成功");
 
         self.set_state(DownloadTaskState::Completed);
         self.emit_download_task_update_event();
+
+        if let Err(err) = self.auto_export() {
+            let err_title = format!("`{comic_title} - {chapter_title}`创建自动导出任务失败");
+            let string_chain = err.to_string_chain();
+            tracing::error!(err_title, message = string_chain);
+        }
+    }
+
+    /// 如果开启了自动导出，则为刚下载完成的章节创建导出任务
+    ///
+    /// 如果此时漫画的所有章节都已下载，且导出格式为PDF，再创建一个合并PDF的导出任务
+    fn auto_export(&self) -> anyhow::Result<()> {
+        let (enable_auto_export, archive, delete_images) = {
+            let config = self.app.get_config();
+            let config = config.read();
+            (
+                config.enable_auto_export,
+                config.auto_export_archive,
+                config.delete_images_after_auto_export,
+            )
+        };
+        if !enable_auto_export {
+            return Ok(());
+        }
+        // 从元数据重新加载漫画，这样章节的`is_downloaded`字段才是最新的
+        let comic_download_dir = self
+            .comic
+            .comic_download_dir
+            .as_ref()
+            .context("`comic_download_dir`字段为`None`")?;
+        let metadata_path = comic_download_dir.join("元数据.json");
+        let comic = Comic::from_metadata(&metadata_path).context("从元数据加载漫画失败")?;
+        let all_chapters_downloaded = comic
+            .chapter_infos
+            .iter()
+            .all(|chapter_info| chapter_info.is_downloaded.unwrap_or(false));
+        let should_merge = archive == ExportArchive::Pdf && all_chapters_downloaded;
+
+        let export_manager = self.app.get_export_manager();
+        let chapter_scope = ExportScope::Chapter(self.chapter_info.chapter_id);
+        // PDF章节的图片还要用于创建合并后的PDF，所以由合并任务删除
+        let delete_chapter_images = delete_images && archive == ExportArchive::Cbz;
+        export_manager.create_export_job(
+            comic.clone(),
+            archive,
+            chapter_scope,
+            delete_chapter_images,
+        );
+        if should_merge {
+            export_manager.create_export_job(comic, archive, ExportScope::Merged, delete_images);
+        }
+
+        Ok(())
     }
 
     async fn download_cover(&self) -> anyhow::Result<()> {
@@ -365,6 +536,53 @@ impl DownloadTask {
         Some(temp_download_dir)
     }
 
+    /// 从临时下载目录的章节元数据中加载之前下载时记录的图片信息
+    fn load_page_records(&self, temp_download_dir: &Path) {
+        let comic_title = &self.comic.name;
+        let chapter_title = &self.chapter_info.chapter_title;
+
+        let metadata_path = temp_download_dir.join("章节元数据.json");
+        if !metadata_path.exists() {
+            return;
+        }
+        let chapter_info = match utils::read_metadata::<ChapterInfo>(&metadata_path) {
+            Ok(chapter_info) => chapter_info,
+            Err(err) => {
+                let err_title = format!("`{comic_title} - {chapter_title}`读取临时章节元数据失败");
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+                return;
+            }
+        };
+        if chapter_info.chapter_id != self.chapter_info.chapter_id {
+            return;
+        }
+
+        let mut page_records = self.page_records.lock();
+        page_records
+            .block_num_overrides
+            .extend(chapter_info.block_num_overrides);
+        page_records
+            .gif_page_exts
+            .extend(chapter_info.gif_page_exts);
+    }
+
+    /// 在临时下载目录中保存带有`page_records`的章节元数据，用于恢复下载
+    fn save_temp_chapter_metadata(&self, temp_download_dir: &Path, page_records: &PageRecords) {
+        let comic_title = &self.comic.name;
+        let chapter_title = &self.chapter_info.chapter_title;
+
+        let mut chapter_info = self.chapter_info.as_ref().clone();
+        chapter_info.chapter_download_dir = Some(temp_download_dir.to_path_buf());
+        chapter_info.block_num_overrides = page_records.block_num_overrides.clone();
+        chapter_info.gif_page_exts = page_records.gif_page_exts.clone();
+        if let Err(err) = chapter_info.save_chapter_metadata() {
+            let err_title = format!("`{comic_title} - {chapter_title}`保存临时章节元数据失败");
+            let string_chain = err.to_string_chain();
+            tracing::error!(err_title, message = string_chain);
+        }
+    }
+
     fn rename_temp_download_dir(&self, temp_download_dir: &PathBuf) -> anyhow::Result<()> {
         let comic_title = &self.comic.name;
         let chapter_title = &self.chapter_info.chapter_title;
@@ -378,6 +596,12 @@ impl DownloadTask {
             std::fs::remove_dir_all(chapter_download_dir)
                 .context(format!("删除 `{}` 失败", chapter_download_dir.display()))?;
         }
+        // 如果章节之前被打包成了cbz，也要删除，否则重新下载的章节会与旧的cbz同时存在
+        let chapter_archive_path = self.chapter_info.get_chapter_archive_path()?;
+        if chapter_archive_path.exists() {
+            std::fs::remove_file(&chapter_archive_path)
+                .context(format!("删除 `{}` 失败", chapter_archive_path.display()))?;
+        }
 
         std::fs::rename(temp_download_dir, chapter_download_dir).context(format!(
             "将 `{}` 重命名为 `{}` 失败",
@@ -419,6 +643,7 @@ impl DownloadTask {
                 return None;
             }
         };
+        let unscramble_gif = self.app.get_config().read().unscramble_gif;
         // 构造图片下载链接
         let urls_with_block_num: Vec<(String, u32)> = chapter_resp_data
             .images
@@ -427,9 +652,9 @@ impl DownloadTask {
                 let file_path = Path::new(&filename);
                 let ext = file_path.extension()?.to_str()?.to_lowercase();
                 let url = format!("https://{IMAGE_DOMAIN}/media/photos/{chapter_id}/{filename}");
-                if ext == "gif" {
+                if ext == "gif" && !unscramble_gif {
                     return Some((url, 0));
-                } else if ext != "webp" {
+                } else if ext != "webp" && ext != "gif" {
                     return None;
                 }
 
@@ -462,18 +687,39 @@ impl DownloadTask {
             }
         };
 
-        let download_format = self.app.get_config().read().download_format;
+        let (download_format, convert_gif_to_webp) = {
+            let config = self.app.get_config();
+            let config = config.read();
+            (config.download_format, config.convert_gif_to_webp)
+        };
         let extension = download_format.extension();
+        let mut page_records = self.page_records.lock();
         for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
-            // path有扩展名，且能转换为utf8，并与`config.download_format`一致或是gif，则保留
-            let should_keep = path
-                .extension()
-                .and_then(|ext| ext.to_str())
-                .is_some_and(|ext| ext == "gif" || ext == extension);
+            // 章节元数据记录了之前下载时的图片信息，保留
+            if path.ends_with("章节元数据.json") {
+                continue;
+            }
+            let index_filename = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or_default();
+            let ext = path.extension().and_then(|ext| ext.to_str());
+            let should_keep = match (extension, page_records.gif_page_exts.get(index_filename)) {
+                // 原图是GIF的图片，扩展名要与记录的一致，且仍是当前配置下GIF保存的格式
+                // 太大的GIF不会转换为动态WebP，所以gif总是允许的
+                (Some(_), Some(gif_ext)) => {
+                    ext == Some(gif_ext.as_str()) && (gif_ext == "gif" || convert_gif_to_webp)
+                }
+                // 其他图片的扩展名要与`config.download_format`一致
+                (Some(extension), None) => ext == Some(extension),
+                // 保存为原格式时，任何格式的图片都可能是原图，全部保留
+                (None, _) => path.is_img(),
+            };
             if should_keep {
                 continue;
             }
-            // 否则删除文件
+            // 否则删除文件，并忘记它的记录，重新下载时会重新记录
+            page_records.remove(index_filename);
             if let Err(err) = std::fs::remove_file(&path).map_err(anyhow::Error::from) {
                 let err_title =
                     format!("`{comic_title}`删除临时下载目录的`{}`失败", path.display());
@@ -682,13 +928,32 @@ impl DownloadImgTask {
         let temp_download_path = &self.temp_download_path;
 
         let index_filename = format!("{:04}", self.index + 1);
-        let download_format = self.app.get_config().read().download_format;
-        let ext = download_format.extension();
-
-        let user_format_path = temp_download_path.join(format!("{index_filename}.{ext}"));
-        let gif_path = temp_download_path.join(format!("{index_filename}.gif"));
+        let (download_format, convert_gif_to_webp) = {
+            let config = self.app.get_config();
+            let config = config.read();
+            (config.download_format, config.convert_gif_to_webp)
+        };
+        // 已下载的图片可能的扩展名，保存为原格式时，原图可能是任何格式
+        // 原图是GIF的图片的扩展名记录在`gif_page_exts`中，与配置对不上的已在开始下载前被清理
+        let gif_page_ext = self
+            .download_task
+            .page_records
+            .lock()
+            .gif_page_exts
+            .get(&index_filename)
+            .cloned();
+        let existing_exts: Vec<&str> = match (download_format.extension(), &gif_page_ext) {
+            (Some(_), Some(gif_page_ext)) => vec![gif_page_ext.as_str()],
+            (Some(ext), None) => vec![ext],
+            (None, _) => vec!["jpg", "png", "webp", "gif"],
+        };
+        let img_exists = existing_exts.iter().any(|ext| {
+            temp_download_path
+                .join(format!("{index_filename}.{ext}"))
+                .exists()
+        });
 
-        if user_format_path.exists() || gif_path.exists() {
+        if img_exists {
             // 如果图片已经存在，直接返回
             self.download_task
                 .downloaded_img_count
@@ -715,19 +980,62 @@ impl DownloadImgTask {
 
         tracing::trace!(url, comic_title, chapter_title, "图片成功下载到内存");
 
-        let save_path = if format == ImageFormat::Gif {
-            gif_path
+        // GIF不受`download_format`影响，保存为GIF或动态WebP
+        let dst_format = if format == ImageFormat::Gif && convert_gif_to_webp {
+            ImageFormat::WebP
+        } else if format == ImageFormat::Gif {
+            ImageFormat::Gif
         } else {
-            user_format_path
+            download_format.image_format(format)
         };
+        let ext = dst_format.extensions_str()[0];
+        let save_path = temp_download_path.join(format!("{index_filename}.{ext}"));
 
         let block_num = self.block_num;
+        let encode_options = EncodeOptions::from_app(&self.app);
         // 保存图片
-        if let Err(err) = save_img(&save_path, download_format, block_num, img_data, format).await {
-            let err_title = format!("保存图片`{url}`失败");
-            let string_chain = err.to_string_chain();
-            tracing::error!(err_title, message = string_chain);
-            return;
+        let save_result = save_img(
+            &self.download_manager,
+            &save_path,
+            dst_format,
+            encode_options,
+            block_num,
+            img_data,
+            format,
+        )
+        .await;
+        let (used_block_num, saved_format) = match save_result {
+            Ok(saved) => saved,
+            Err(err) => {
+                let err_title = format!("保存图片`{url}`失败");
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+                return;
+            }
+        };
+        let is_gif = format == ImageFormat::Gif;
+        if used_block_num != block_num {
+            tracing::warn!(
+                url,
+                comic_title,
+                chapter_title,
+                "计算出的分块数`{block_num}`拼接后的图片不连续，改用检测出的分块数`{used_block_num}`"
+            );
+        }
+        if used_block_num != block_num || is_gif {
+            // 持有锁直到保存完成，避免并发保存时旧的记录覆盖新的
+            let mut page_records = self.download_task.page_records.lock();
+            if used_block_num != block_num {
+                page_records
+                    .block_num_overrides
+                    .insert(index_filename.clone(), used_block_num);
+            }
+            if is_gif {
+                let saved_ext = saved_format.extensions_str()[0].to_string();
+                page_records.gif_page_exts.insert(index_filename, saved_ext);
+            }
+            self.download_task
+                .save_temp_chapter_metadata(temp_download_path, &page_records);
         }
 
         tracing::trace!(
@@ -830,105 +1138,469 @@ fn calculate_block_num(scramble_id: i64, id: i64, filename: &str) -> u32 {
     }
 }
 
+/// 将图片拼接后以`dst_format`格式保存到`save_path`，返回拼接实际使用的分块数
+///
+/// 无需拼接且格式与`dst_format`一致的图片，直接保存原始数据，不重新编码
+///
+/// GIF会解码所有帧并逐帧拼接，再编码为GIF或动态WebP(由`dst_format`决定)
+///
+/// 如果开启了`detect_block_num`，解码后会检查拼接结果，必要时改用检测出的分块数
+///
+/// 返回实际使用的分块数和实际保存的格式
 async fn save_img(
+    download_manager: &DownloadManager,
     save_path: &Path,
-    download_format: DownloadFormat,
+    dst_format: ImageFormat,
+    encode_options: EncodeOptions,
     block_num: u32,
     src_img_data: Bytes,
     src_format: ImageFormat,
-) -> anyhow::Result<()> {
-    // TODO: 如果src_format是WebP，download_format也是WebP，且block_num为0，也可以直接保存
-    if src_format == ImageFormat::Gif {
-        // 如果是GIF格式，直接保存
+) -> anyhow::Result<(u32, ImageFormat)> {
+    if block_num == 0 && src_format == dst_format {
         std::fs::write(save_path, src_img_data)
             .context(format!("保存图片`{}`失败", save_path.display()))?;
-        return Ok(());
+        return Ok((block_num, dst_format));
     }
 
+    let should_detect_block_num = download_manager.app.get_config().read().detect_block_num;
     // 图像处理的闭包
     let save_path = save_path.to_path_buf();
-    let process_img = move || -> anyhow::Result<()> {
-        let mut src_img = image::load_from_memory(&src_img_data)
-            .context("解码图片失败")?
-            .to_rgb8();
+    let process_img = move || -> anyhow::Result<(u32, ImageFormat)> {
+        if src_format == ImageFormat::Gif {
+            let (dst_img_data, format, block_num) = process_gif(
+                &src_img_data,
+                dst_format,
+                encode_options,
+                block_num,
+                should_detect_block_num,
+            )?;
+            // 太大的GIF不会转换为动态WebP，而是保存为GIF
+            let save_path = save_path.with_extension(format.extensions_str()[0]);
+            std::fs::write(&save_path, dst_img_data)
+                .context(format!("保存图片`{}`失败", save_path.display()))?;
+            return Ok((block_num, format));
+        }
+        let src_img = image::load_from_memory(&src_img_data).context("解码图片失败")?;
+        // 保留原图的颜色类型和透明通道
+        let src_img = into_8bit(src_img);
+        // 计算出的分块数为0的图片没有被打乱，不检测，以免把内容本身的不连续误判为分块边界
+        let block_num = if should_detect_block_num && block_num != 0 {
+            detect_block_num(&src_img, block_num)
+        } else {
+            block_num
+        };
         // 如果无需拼接，直接根据格式保存图片
         // 否则拼接图片
         let dst_img = if block_num == 0 {
             src_img
         } else {
-            stitch_img(&mut src_img, block_num)
+            stitch_dynamic_img(&src_img, block_num).context("拼接图片失败")?
         };
-        // 用来存图片编码后的数据
-        let mut dst_img_data = Vec::new();
-        match download_format {
-            DownloadFormat::Jpeg => {
-                dst_img.write_to(&mut Cursor::new(&mut dst_img_data), ImageFormat::Jpeg)?;
-            }
-            DownloadFormat::Png => {
-                let encoder = PngEncoder::new_with_quality(
-                    Cursor::new(&mut dst_img_data),
-                    png::CompressionType::Best,
-                    png::FilterType::default(),
-                );
-                dst_img.write_with_encoder(encoder)?;
-            }
-            DownloadFormat::Webp => {
-                dst_img.write_to(&mut Cursor::new(&mut dst_img_data), ImageFormat::WebP)?;
-            }
-        }
+        let dst_img_data = encode_img(&dst_img, dst_format, encode_options)
+            .context(format!("将图片编码为{dst_format:?}失败"))?;
         // 保存编码后的图片数据
         std::fs::write(&save_path, dst_img_data)
             .context(format!("保存图片`{}`失败", save_path.display()))?;
-        Ok(())
+        Ok((block_num, dst_format))
     };
-    // 因为图像处理是CPU密集型操作，所以使用rayon并发处理
-    let (sender, receiver) = tokio::sync::oneshot::channel::<anyhow::Result<()>>();
-    rayon::spawn(move || {
+    // 等待处理队列有空位，处理跟不上下载时，下载任务会在这里等待，避免大量解码后的图片堆积在内存中
+    let permit = download_manager
+        .img_process_sem
+        .clone()
+        .acquire_owned()
+        .await
+        .context("获取处理图片的permit失败")?;
+    let queued = download_manager.img_process_queued.clone();
+    queued.fetch_add(1, Ordering::Relaxed);
+    // 因为图像处理是CPU密集型操作，所以在图片处理线程池中处理
+    let (sender, receiver) = tokio::sync::oneshot::channel::<anyhow::Result<(u32, ImageFormat)>>();
+    download_manager.img_process_pool.spawn(move || {
         let _ = sender.send(process_img());
+        // 即使下载任务已被取消，也要等处理完成后才释放队列的位置
+        queued.fetch_sub(1, Ordering::Relaxed);
+        drop(permit);
     });
     // 在tokio任务中等待rayon任务的完成，避免阻塞worker threads
     receiver.await?
 }
 
-/// 拼接图片
-fn stitch_img(src_img: &mut RgbImage, block_num: u32) -> RgbImage {
-    // 如果block_num不为0，需要将图片拼接后再保存
+/// 下载图片时的编码参数
+#[derive(Debug, Clone, Copy)]
+struct EncodeOptions {
+    jpeg_quality: u8,
+    png_compression: PngCompression,
+    /// WebP是否无损编码，为`false`时用`webp_quality`有损编码
+    webp_lossless: bool,
+    webp_quality: u8,
+    avif_quality: u8,
+    /// AVIF的编码速度，范围为1-10，越慢压缩率越高
+    avif_speed: u8,
+}
+
+impl EncodeOptions {
+    fn from_app(app: &AppHandle) -> Self {
+        let config = app.get_config();
+        let config = config.read();
+        EncodeOptions {
+            jpeg_quality: config.jpeg_quality.clamp(1, 100),
+            png_compression: config.png_compression,
+            webp_lossless: config.webp_lossless,
+            webp_quality: config.webp_quality.clamp(1, 100),
+            avif_quality: config.avif_quality.clamp(1, 100),
+            avif_speed: config.avif_speed.clamp(1, 10),
+        }
+    }
+}
+
+/// 用`options`中对应格式的参数将`img`编码为`format`格式
+///
+/// 尽量保留`img`的颜色类型，只有格式不支持时才转换，例如JPEG不支持透明通道
+fn encode_img(
+    img: &DynamicImage,
+    format: ImageFormat,
+    options: EncodeOptions,
+) -> anyhow::Result<Vec<u8>> {
+    // 用来存图片编码后的数据
+    let mut img_data = Vec::new();
+    match format {
+        ImageFormat::Jpeg => {
+            // JPEG只支持灰度和RGB，透明通道会被丢弃
+            let img = match img {
+                DynamicImage::ImageLuma8(_) | DynamicImage::ImageRgb8(_) => Cow::Borrowed(img),
+                _ if img.color().has_color() => Cow::Owned(DynamicImage::ImageRgb8(img.to_rgb8())),
+                _ => Cow::Owned(DynamicImage::ImageLuma8(img.to_luma8())),
+            };
+            let encoder = JpegEncoder::new_with_quality(&mut img_data, options.jpeg_quality);
+            img.write_with_encoder(encoder)?;
+        }
+        ImageFormat::Png => {
+            let encoder = PngEncoder::new_with_quality(
+                Cursor::new(&mut img_data),
+                options.png_compression.into(),
+                png::FilterType::default(),
+            );
+            img.write_with_encoder(encoder)?;
+        }
+        // image只支持无损编码WebP，有损编码用libwebp，而libwebp只支持RGB和RGBA
+        ImageFormat::WebP if !options.webp_lossless => {
+            let (width, height) = img.dimensions();
+            let quality = f32::from(options.webp_quality);
+            let webp_data = if img.color().has_alpha() {
+                let rgba_img = img.to_rgba8();
+                webp::Encoder::from_rgba(rgba_img.as_raw(), width, height)
+                    .encode_simple(false, quality)
+            } else {
+                let rgb_img = img.to_rgb8();
+                webp::Encoder::from_rgb(rgb_img.as_raw(), width, height)
+                    .encode_simple(false, quality)
+            }
+            .map_err(|err| anyhow!("libwebp编码失败: {err:?}"))?;
+            img_data = webp_data.to_vec();
+        }
+        ImageFormat::Avif => {
+            let encoder = AvifEncoder::new_with_speed_quality(
+                &mut img_data,
+                options.avif_speed,
+                options.avif_quality,
+            );
+            img.write_with_encoder(encoder)?;
+        }
+        _ => img.write_to(&mut Cursor::new(&mut img_data), format)?,
+    }
+    Ok(img_data)
+}
+
+/// 编码动态WebP时所有帧都要保留在内存中，拼接后的帧总大小超过这个值时改为保存为GIF
+const MAX_ANIMATED_WEBP_FRAMES_BYTES: usize = 256 * 1024 * 1024;
+
+/// 解码GIF的帧，逐帧拼接后编码为`dst_format`格式(GIF或动态WebP)，
+/// 返回编码后的数据、实际编码的格式和拼接实际使用的分块数
+///
+/// 编码为GIF时逐帧解码、拼接和编码，内存中只保留一帧。
+/// 编码为动态WebP时所有帧都要保留到编码结束，帧的总大小超过`MAX_ANIMATED_WEBP_FRAMES_BYTES`时改为编码为GIF
+fn process_gif(
+    src_img_data: &[u8],
+    dst_format: ImageFormat,
+    encode_options: EncodeOptions,
+    block_num: u32,
+    should_detect_block_num: bool,
+) -> anyhow::Result<(Vec<u8>, ImageFormat, u32)> {
+    // 所有帧的打乱方式相同，用第一帧检测分块数即可
+    // 计算出的分块数为0的图片没有被打乱，不检测
+    let block_num = if should_detect_block_num && block_num != 0 {
+        let first_frame = gif_frames(src_img_data)?
+            .next()
+            .context("GIF中没有任何帧")?
+            .context("解码GIF的第一帧失败")?;
+        let first_img = DynamicImage::ImageRgba8(first_frame.into_buffer());
+        detect_block_num(&first_img, block_num)
+    } else {
+        block_num
+    };
+
+    if dst_format == ImageFormat::WebP {
+        if let Some(frames) = collect_stitched_frames(src_img_data, block_num)? {
+            let dst_img_data =
+                encode_animated_webp(&frames, encode_options).context("将GIF编码为动态WebP失败")?;
+            return Ok((dst_img_data, ImageFormat::WebP, block_num));
+        }
+        tracing::warn!(
+            "GIF拼接后的帧总大小超过了{}MiB，改为保存为GIF",
+            MAX_ANIMATED_WEBP_FRAMES_BYTES / 1024 / 1024
+        );
+    }
+
+    let mut dst_img_data = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut dst_img_data);
+        encoder.set_repeat(Repeat::Infinite)?;
+        let mut frame_count = 0;
+        for frame in gif_frames(src_img_data)? {
+            let frame = frame.context("解码GIF的帧失败")?;
+            let frame = stitch_frame(frame, block_num).context("拼接GIF的帧失败")?;
+            encoder.encode_frame(frame).context("将帧编码为GIF失败")?;
+            frame_count += 1;
+        }
+        if frame_count == 0 {
+            return Err(anyhow!("GIF中没有任何帧"));
+        }
+    }
+
+    Ok((dst_img_data, ImageFormat::Gif, block_num))
+}
+
+/// 逐帧解码GIF，解码出的每一帧都已经合成为完整的画布大小
+fn gif_frames(src_img_data: &[u8]) -> anyhow::Result<image::Frames<'_>> {
+    let decoder = GifDecoder::new(Cursor::new(src_img_data)).context("解析GIF失败")?;
+    Ok(decoder.into_frames())
+}
+
+/// 用`block_num`拼接`frame`，`block_num`为0时原样返回
+fn stitch_frame(frame: Frame, block_num: u32) -> anyhow::Result<Frame> {
+    if block_num == 0 {
+        return Ok(frame);
+    }
+    let (left, top, delay) = (frame.left(), frame.top(), frame.delay());
+    let buffer = stitch_img(frame.buffer(), block_num)?;
+    Ok(Frame::from_parts(buffer, left, top, delay))
+}
+
+/// 解码并拼接GIF的所有帧，帧的总大小超过`MAX_ANIMATED_WEBP_FRAMES_BYTES`时返回`None`
+fn collect_stitched_frames(
+    src_img_data: &[u8],
+    block_num: u32,
+) -> anyhow::Result<Option<Vec<Frame>>> {
+    let mut frames = Vec::new();
+    let mut total_bytes = 0;
+    for frame in gif_frames(src_img_data)? {
+        let frame = frame.context("解码GIF的帧失败")?;
+        total_bytes += frame.buffer().as_raw().len();
+        if total_bytes > MAX_ANIMATED_WEBP_FRAMES_BYTES {
+            return Ok(None);
+        }
+        frames.push(stitch_frame(frame, block_num).context("拼接GIF的帧失败")?);
+    }
+    if frames.is_empty() {
+        return Err(anyhow!("GIF中没有任何帧"));
+    }
+    Ok(Some(frames))
+}
+
+/// 用libwebp将`frames`编码为无限循环的动态WebP，是否无损及质量与WebP的设置相同
+fn encode_animated_webp(frames: &[Frame], options: EncodeOptions) -> anyhow::Result<Vec<u8>> {
+    let first_frame = frames.first().context("没有任何帧")?;
+    let (width, height) = first_frame.buffer().dimensions();
+
+    let mut config = webp::WebPConfig::new().map_err(|()| anyhow!("初始化WebPConfig失败"))?;
+    config.lossless = i32::from(options.webp_lossless);
+    config.quality = f32::from(options.webp_quality);
+
+    let mut encoder = webp::AnimEncoder::new(width, height, &config);
+    encoder.set_loop_count(0);
+    // 每一帧的时间戳是它开始显示的时间，单位为毫秒
+    let mut timestamp_ms: i32 = 0;
+    for frame in frames {
+        encoder.add_frame(webp::AnimFrame::from_rgba(
+            frame.buffer().as_raw(),
+            width,
+            height,
+            timestamp_ms,
+        ));
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        let delay_ms = numer / denom.max(1);
+        timestamp_ms = timestamp_ms.saturating_add(i32::try_from(delay_ms).unwrap_or(i32::MAX));
+    }
+    let webp_data = encoder
+        .try_encode()
+        .map_err(|err| anyhow!("libwebp编码失败: {err:?}"))?;
+
+    Ok(webp_data.to_vec())
+}
+
+/// 将`img`转换为8位的Luma、LumaA、Rgb或Rgba，保留是否有颜色和透明通道
+fn into_8bit(img: DynamicImage) -> DynamicImage {
+    match img {
+        DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_)
+        | DynamicImage::ImageRgb8(_)
+        | DynamicImage::ImageRgba8(_) => img,
+        _ => {
+            let color = img.color();
+            match (color.has_color(), color.has_alpha()) {
+                (false, false) => DynamicImage::ImageLuma8(img.into_luma8()),
+                (false, true) => DynamicImage::ImageLumaA8(img.into_luma_alpha8()),
+                (true, false) => DynamicImage::ImageRgb8(img.into_rgb8()),
+                (true, true) => DynamicImage::ImageRgba8(img.into_rgba8()),
+            }
+        }
+    }
+}
+
+/// 拼接8位的`img`，拼接后的颜色类型与`img`一致
+fn stitch_dynamic_img(img: &DynamicImage, block_num: u32) -> anyhow::Result<DynamicImage> {
+    let stitched_img = match img {
+        DynamicImage::ImageLuma8(img) => DynamicImage::ImageLuma8(stitch_img(img, block_num)?),
+        DynamicImage::ImageLumaA8(img) => DynamicImage::ImageLumaA8(stitch_img(img, block_num)?),
+        DynamicImage::ImageRgb8(img) => DynamicImage::ImageRgb8(stitch_img(img, block_num)?),
+        DynamicImage::ImageRgba8(img) => DynamicImage::ImageRgba8(stitch_img(img, block_num)?),
+        _ => return Err(anyhow!("不支持拼接颜色类型为`{:?}`的图片", img.color())),
+    };
+    Ok(stitched_img)
+}
+
+/// 拼接图片，适用于任意像素类型
+///
+/// 原图从下往上被切成了`block_num`块，每块在原图中都是连续的行，
+/// 所以按目标顺序逐块整段复制原始数据即可，无需逐像素复制
+fn stitch_img<P: Pixel>(
+    src_img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    block_num: u32,
+) -> anyhow::Result<ImageBuffer<P, Vec<P::Subpixel>>> {
     let (width, height) = src_img.dimensions();
-    // 创建一张空的图片，尺寸与原图相同，用于拼接分块
-    let mut stitched_img = image::ImageBuffer::new(width, height);
+    // 每行像素在原始数据中的长度
+    let row_len = width as usize * usize::from(P::CHANNEL_COUNT);
+    let src_data = src_img.as_raw();
+    let mut stitched_data = Vec::with_capacity(src_data.len());
     // 计算原图像的高度除以num的余数
     let remainder_height = height % block_num;
-    // 将图片切分为block_num块并拼接
+    // 按目标图像从上到下的顺序依次复制每一块
     for i in 0..block_num {
         // 计算当前块的标准高度
         let mut block_height = height / block_num;
         // 计算源图像中当前块的Y轴起点位置
         let src_img_y_start = height - (block_height * (i + 1)) - remainder_height;
-        // 计算目标图像中当前块的Y轴起点位置
-        let mut dst_img_y_start = block_height * i;
         // 第一块需要加上余数高度，以确保拼接完整
         if i == 0 {
             block_height += remainder_height;
-        } else {
-            dst_img_y_start += remainder_height;
         }
-        // 逐行复制当前块
-        for y in 0..block_height {
-            let src_y = src_img_y_start + y;
-            let dst_y = dst_img_y_start + y;
-            // 复制整行像素到目标图像
-            for x in 0..width {
-                stitched_img.put_pixel(x, dst_y, *src_img.get_pixel(x, src_y));
-            }
+        // 整块复制
+        let start = src_img_y_start as usize * row_len;
+        let end = start + block_height as usize * row_len;
+        stitched_data.extend_from_slice(&src_data[start..end]);
+    }
+
+    ImageBuffer::from_raw(width, height, stitched_data).context("拼接后的图片数据长度与尺寸不符")
+}
+
+/// `calculate_block_num`可能算出的所有分块数
+const CANDIDATE_BLOCK_NUMS: [u32; 11] = [0, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20];
+/// 计算相邻行差异的基准值时，最多采样的行数
+const ROW_DIFF_SAMPLE_ROWS: u32 = 512;
+/// 检测出的分块数的可信度至少要比计算出的分块数高这么多，才改用检测出的分块数
+const BLOCK_NUM_EVIDENCE_MARGIN: f64 = 20.0;
+
+/// 检查原图是否像是用`block_num`打乱的，如果不像，从`CANDIDATE_BLOCK_NUMS`中选出最像的分块数
+///
+/// 如果原图确实是用`n`打乱的，那么原图中`n`的分块边界处上下两行不连续，而用`n`拼接后分块的连接处是连续的，
+/// 所以用这两处不连续程度的差作为`n`的可信度，可信度最高的就是检测出的分块数
+///
+/// `img`必须是8位的图片
+fn detect_block_num(img: &DynamicImage, block_num: u32) -> u32 {
+    let height = img.height();
+    if height < 2 {
+        return block_num;
+    }
+    let raw = img.as_bytes();
+    let row_len = raw.len() / height as usize;
+    // 原图中第`a`行与第`b`行的平均每字节差异
+    #[allow(clippy::cast_precision_loss)]
+    let row_diff = |a: u32, b: u32| -> f64 {
+        let row_a = &raw[a as usize * row_len..][..row_len];
+        let row_b = &raw[b as usize * row_len..][..row_len];
+        let sum: u64 = row_a
+            .iter()
+            .zip(row_b)
+            .map(|(x, y)| u64::from(x.abs_diff(*y)))
+            .sum();
+        sum as f64 / row_len as f64
+    };
+    // 以均匀采样的相邻行差异的中位数作为基准，打乱产生的断层很少，不会影响中位数
+    let step = (height / ROW_DIFF_SAMPLE_ROWS).max(1);
+    let mut sample_diffs: Vec<f64> = (1..height)
+        .step_by(step as usize)
+        .map(|y| row_diff(y - 1, y))
+        .collect();
+    sample_diffs.sort_by(f64::total_cmp);
+    let baseline = sample_diffs[sample_diffs.len() / 2];
+    // 不连续程度，即差异超出基准的部分
+    let discontinuity = |a: u32, b: u32| (row_diff(a, b) - baseline * 2.0).max(0.0);
+    // `n`的可信度
+    #[allow(clippy::cast_precision_loss)]
+    let evidence = |n: u32| -> f64 {
+        if n == 0 || height < n {
+            return 0.0;
+        }
+        let block_height = height / n;
+        let remainder_height = height % n;
+        let (mut src_sum, mut join_sum) = (0.0, 0.0);
+        for k in 1..n {
+            // 原图中分块的边界
+            let y = block_height * k;
+            src_sum += discontinuity(y - 1, y);
+            // 拼接后分块的连接处
+            let y = remainder_height + block_height * k;
+            join_sum += discontinuity(
+                stitched_src_row(height, n, y - 1),
+                stitched_src_row(height, n, y),
+            );
         }
+        (src_sum - join_sum) / f64::from(n - 1)
+    };
+
+    let expected_evidence = evidence(block_num);
+    let (best_block_num, best_evidence) = CANDIDATE_BLOCK_NUMS
+        .iter()
+        .map(|&n| (n, evidence(n)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .unwrap_or((block_num, expected_evidence));
+    // 明显更可信才改用检测出的分块数，避免分镜边框等本来就不连续的内容导致误判
+    if best_evidence > expected_evidence + BLOCK_NUM_EVIDENCE_MARGIN {
+        best_block_num
+    } else {
+        block_num
     }
+}
 
-    stitched_img
+/// 用`block_num`拼接后，第`y`行来自原图的哪一行，与`stitch_img`的拼接方式一致
+fn stitched_src_row(height: u32, block_num: u32, y: u32) -> u32 {
+    if block_num == 0 || height < block_num {
+        return y;
+    }
+    let block_height = height / block_num;
+    let remainder_height = height % block_num;
+    // 第一块包含余数高度
+    let (i, offset) = if y < block_height + remainder_height {
+        (0, y)
+    } else {
+        let i = (y - remainder_height) / block_height;
+        (i, y - remainder_height - block_height * i)
+    };
+    height - block_height * (i + 1) - remainder_height + offset
 }
 
 impl Comic {
     /// 根据fmt更新`comic_download_dir`和`chapter_infos.chapter_download_dir`字段
-    fn update_download_dir_fields_by_fmt(&mut self, app: &AppHandle) -> anyhow::Result<()> {
+    pub fn update_download_dir_fields_by_fmt(&mut self, app: &AppHandle) -> anyhow::Result<()> {
         if self.chapter_infos.is_empty() {
             return Err(anyhow!("没有章节信息，无法更新下载目录字段"));
         }
@@ -945,6 +1617,7 @@ impl Comic {
                 chapter_id: chapter_info.chapter_id,
                 chapter_title: chapter_info.chapter_title.clone(),
                 order: chapter_info.order,
+                favorite_folder: self.favorite_folder.clone().unwrap_or_default(),
             };
 
             let chapter_download_dir =
@@ -983,6 +1656,8 @@ pub struct DirFmtParams {
     pub chapter_id: i64,
     pub chapter_title: String,
     pub order: i64,
+    /// 不是从指定收藏夹下载的漫画为空字符串，该层目录会被忽略
+    pub favorite_folder: String,
 }
 
 impl ChapterInfo {
@@ -990,41 +1665,13 @@ impl ChapterInfo {
         app: &AppHandle,
         fmt_params: &DirFmtParams,
     ) -> anyhow::Result<PathBuf> {
-        use strfmt::strfmt;
-
-        let json_value =
-            serde_json::to_value(fmt_params).context("将DirFmtParams转为serde_json::Value失败")?;
-
-        let json_map = json_value.as_object().context("DirFmtParams不是JSON对象")?;
-
-        let vars: HashMap<String, String> = json_map
-            .into_iter()
-            .map(|(k, v)| {
-                let key = k.clone();
-                let value = match v {
-                    serde_json::Value::String(s) => s.clone(),
-                    _ => v.to_string(),
-                };
-                (key, value)
-            })
-            .collect();
-
         let (download_dir, dir_fmt) = {
             let config = app.get_config();
             let config = config.read();
             (config.download_dir.clone(), config.dir_fmt.clone())
         };
 
-        let dir_fmt_parts: Vec<&str> = dir_fmt.split('/').collect();
-
-        let mut dir_names = Vec::new();
-        for fmt in dir_fmt_parts {
-            let dir_name = strfmt(fmt, &vars).context("格式化目录名失败")?;
-            let dir_name = filename_filter(&dir_name);
-            if !dir_name.is_empty() {
-                dir_names.push(dir_name);
-            }
-        }
+        let dir_names = utils::fmt_path_parts(&dir_fmt, fmt_params).context("格式化目录名失败")?;
 
         if dir_names.len() < 2 {
             let err_msg =
@@ -1055,7 +1702,157 @@ impl ChapterInfo {
             chapter_download_dir.display()
         ))?;
 
-        let temp_download_dir = parent.join(format!(".下载中-{chapter_download_dir_name}"));
+        let temp_download_dir = parent.join(format!(
+            "{TEMP_DOWNLOAD_DIR_PREFIX}{chapter_download_dir_name}"
+        ));
         Ok(temp_download_dir)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use image::{ImageBuffer, Luma, LumaA, Pixel, Rgb, Rgba};
+
+    use super::stitch_img;
+
+    /// 逐像素复制的旧拼接算法，作为整块复制的新算法的参照
+    fn stitch_img_per_pixel<P: Pixel>(
+        src_img: &ImageBuffer<P, Vec<P::Subpixel>>,
+        block_num: u32,
+    ) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        let (width, height) = src_img.dimensions();
+        let mut stitched_img = ImageBuffer::new(width, height);
+        let remainder_height = height % block_num;
+        for i in 0..block_num {
+            let mut block_height = height / block_num;
+            let src_img_y_start = height - (block_height * (i + 1)) - remainder_height;
+            let mut dst_img_y_start = block_height * i;
+            if i == 0 {
+                block_height += remainder_height;
+            } else {
+                dst_img_y_start += remainder_height;
+            }
+            for y in 0..block_height {
+                let src_y = src_img_y_start + y;
+                let dst_y = dst_img_y_start + y;
+                for x in 0..width {
+                    stitched_img.put_pixel(x, dst_y, *src_img.get_pixel(x, src_y));
+                }
+            }
+        }
+        stitched_img
+    }
+
+    /// 用xorshift生成确定的伪随机数据，每个像素都不同，这样复制错任何一行都能被发现
+    #[allow(clippy::cast_possible_truncation)]
+    fn synthetic_img<P: Pixel<Subpixel = u8>>(
+        width: u32,
+        height: u32,
+        seed: u64,
+    ) -> ImageBuffer<P, Vec<u8>> {
+        let len = width as usize * height as usize * usize::from(P::CHANNEL_COUNT);
+        let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+        let data = (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        ImageBuffer::from_raw(width, height, data).unwrap()
+    }
+
+    /// 宽度包含1和奇数，高度包含小于分块数、能整除和不能整除分块数的情况
+    const WIDTHS: [u32; 3] = [1, 7, 64];
+    const HEIGHTS: [u32; 7] = [1, 2, 3, 19, 20, 101, 1000];
+    const BLOCK_NUMS: [u32; 6] = [1, 2, 3, 10, 18, 20];
+
+    fn assert_same_as_per_pixel<P: Pixel<Subpixel = u8>>() {
+        for width in WIDTHS {
+            for height in HEIGHTS {
+                for block_num in BLOCK_NUMS {
+                    let seed = u64::from(width * 10_000 + height * 100 + block_num);
+                    let src_img = synthetic_img::<P>(width, height, seed);
+                    let expected = stitch_img_per_pixel(&src_img, block_num);
+                    let actual = stitch_img(&src_img, block_num).unwrap();
+                    assert_eq!(actual.dimensions(), expected.dimensions());
+                    assert!(
+                        actual.as_raw() == expected.as_raw(),
+                        "{}通道，{width}x{height}，分块数{block_num}的拼接结果与旧算法不同",
+                        P::CHANNEL_COUNT
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stitch_luma8_same_as_per_pixel() {
+        assert_same_as_per_pixel::<Luma<u8>>();
+    }
+
+    #[test]
+    fn stitch_luma_alpha8_same_as_per_pixel() {
+        assert_same_as_per_pixel::<LumaA<u8>>();
+    }
+
+    #[test]
+    fn stitch_rgb8_same_as_per_pixel() {
+        assert_same_as_per_pixel::<Rgb<u8>>();
+    }
+
+    #[test]
+    fn stitch_rgba8_same_as_per_pixel() {
+        assert_same_as_per_pixel::<Rgba<u8>>();
+    }
+
+    #[test]
+    fn stitch_with_one_block_keeps_img() {
+        let src_img = synthetic_img::<Rgb<u8>>(31, 77, 1);
+        let stitched_img = stitch_img(&src_img, 1).unwrap();
+        assert_eq!(stitched_img.as_raw(), src_img.as_raw());
+    }
+
+    #[test]
+    fn stitch_with_two_blocks_swaps_halves() {
+        // 高度为奇数时，余下的一行属于原图底部的那一块，拼接后在最上面
+        let src_img = synthetic_img::<Luma<u8>>(5, 9, 2);
+        let stitched_img = stitch_img(&src_img, 2).unwrap();
+        let row_len = 5;
+        let (src_top, src_bottom) = src_img.as_raw().split_at(4 * row_len);
+        let (dst_top, dst_bottom) = stitched_img.as_raw().split_at(5 * row_len);
+        assert_eq!(dst_top, src_bottom);
+        assert_eq!(dst_bottom, src_top);
+    }
+
+    /// 对比新旧算法拼接长条漫画页的耗时，用
+    /// `cargo test --release stitch_img_benchmark -- --ignored --nocapture`运行
+    #[test]
+    #[ignore = "基准测试，需要手动运行"]
+    fn stitch_img_benchmark() {
+        const ROUNDS: u32 = 10;
+        let src_img = synthetic_img::<Rgb<u8>>(800, 12_000, 3);
+        let block_num = 10;
+
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            std::hint::black_box(stitch_img_per_pixel(&src_img, block_num));
+        }
+        let per_pixel_elapsed = start.elapsed() / ROUNDS;
+
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            std::hint::black_box(stitch_img(&src_img, block_num).unwrap());
+        }
+        let block_copy_elapsed = start.elapsed() / ROUNDS;
+
+        println!(
+            "800x12000 RGB8，分块数{block_num}: 逐像素复制 {per_pixel_elapsed:?}，整块复制 {block_copy_elapsed:?}，快了{:.1}倍",
+            per_pixel_elapsed.as_secs_f64() / block_copy_elapsed.as_secs_f64()
+        );
+        assert!(block_copy_elapsed < per_pixel_elapsed);
+    }
+}
@@ -10,8 +10,11 @@ use crate::{
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(rename_all = "camelCase")]
 pub struct DownloadSpeedEvent {
     pub speed: String,
+    /// 已下载但还没处理完的图片数量
+    pub queued_img_count: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
@@ -65,8 +68,54 @@ pub enum DownloadAllFavoritesEvent {
     #[serde(rename_all = "camelCase")]
     EndCreateDownloadTasks { comic_id: i64 },
 
+    /// `cancelled`为`true`时，只处理了`total`个漫画中的`processed_count`个
+    #[serde(rename_all = "camelCase")]
+    GetComicsEnd {
+        cancelled: bool,
+        processed_count: i64,
+        total: i64,
+        created_task_count: i64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(tag = "event", content = "data")]
+pub enum DownloadComicListEvent {
+    #[serde(rename_all = "camelCase")]
+    GetComicListStart,
+
+    /// 获取完第`page`页，`found_count`为目前找到的未下载的漫画数量
+    #[serde(rename_all = "camelCase")]
+    GetComicListProgress { page: i64, found_count: i64 },
+
+    #[serde(rename_all = "camelCase")]
+    GetComicsProgress { current: i64, total: i64 },
+
+    #[serde(rename_all = "camelCase")]
+    StartCreateDownloadTasks {
+        comic_id: i64,
+        comic_title: String,
+        current: i64,
+        total: i64,
+    },
+
+    #[serde(rename_all = "camelCase")]
+    CreatingDownloadTask { comic_id: i64, current: i64 },
+
+    #[serde(rename_all = "camelCase")]
+    EndCreateDownloadTasks { comic_id: i64 },
+
+    /// `skipped_count`为列表中已下载而被跳过的漫画数量
+    ///
+    /// `cancelled`为`true`时，只处理了`total`个漫画中的`processed_count`个
     #[serde(rename_all = "camelCase")]
-    GetComicsEnd,
+    GetComicsEnd {
+        cancelled: bool,
+        processed_count: i64,
+        total: i64,
+        skipped_count: i64,
+        created_task_count: i64,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
@@ -92,8 +141,14 @@ pub enum UpdateDownloadedComicsEvent {
     #[serde(rename_all = "camelCase")]
     CreateDownloadTasksEnd { comic_id: i64 },
 
+    /// `cancelled`为`true`时，只处理了`total`个漫画中的`processed_count`个
     #[serde(rename_all = "camelCase")]
-    GetComicEnd,
+    GetComicEnd {
+        cancelled: bool,
+        processed_count: i64,
+        total: i64,
+        created_task_count: i64,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
@@ -157,3 +212,63 @@ pub struct LogEvent {
     #[serde(rename = "line_number")]
     pub line_number: i64,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(tag = "event", content = "data")]
+pub enum CheckLibraryUpdatesEvent {
+    #[serde(rename_all = "camelCase")]
+    Start { total: i64 },
+
+    #[serde(rename_all = "camelCase")]
+    Progress {
+        comic_title: String,
+        current: i64,
+        total: i64,
+    },
+
+    #[serde(rename_all = "camelCase")]
+    End {
+        cancelled: bool,
+        update_count: i64,
+        failed_count: i64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(tag = "event", content = "data")]
+pub enum RefreshLibraryMetadataEvent {
+    #[serde(rename_all = "camelCase")]
+    Start { total: i64 },
+
+    #[serde(rename_all = "camelCase")]
+    Progress {
+        comic_title: String,
+        current: i64,
+        total: i64,
+    },
+
+    #[serde(rename_all = "camelCase")]
+    End {
+        cancelled: bool,
+        refreshed_count: i64,
+        renamed_count: i64,
+        moved_count: i64,
+        failed_count: i64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type, Event)]
+#[serde(tag = "event", content = "data")]
+pub enum DiskSpaceEvent {
+    /// `path`所在磁盘的剩余空间低于阈值，所有任务已暂停
+    #[serde(rename_all = "camelCase")]
+    Low {
+        path: PathBuf,
+        available_mb: u64,
+        min_free_space_mb: u64,
+    },
+
+    /// 剩余空间已恢复，因空间不足而被暂停的任务已继续
+    #[serde(rename_all = "camelCase")]
+    Recovered,
+}
@@ -1,28 +1,36 @@
 use std::{
-    collections::BTreeMap,
-    io::{Read, Write},
+    io::{Cursor, Read, Write},
     path::{Path, PathBuf},
-    sync::{atomic::AtomicU32, Arc},
+    sync::atomic::Ordering,
 };
 
 use anyhow::{anyhow, Context};
-
-use lopdf::{
-    content::{Content, Operation},
-    dictionary, Bookmark, Document, Object, Stream,
+use flate2::write::ZlibEncoder;
+use image::{
+    codecs::jpeg::{JpegDecoder, JpegEncoder},
+    ColorType, DynamicImage, GenericImageView, GrayImage, ImageDecoder, ImageFormat, Luma, Rgb,
+    RgbImage,
 };
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
 use parking_lot::Mutex;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use specta::Type;
 use tauri::AppHandle;
 use tauri_specta::Event;
-use zip::{write::SimpleFileOptions, ZipWriter};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
 
 use crate::{
     events::{ExportCbzEvent, ExportPdfEvent},
-    extensions::{AnyhowErrorToStringChain, PathIsImg},
-    types::{ChapterInfo, Comic, ComicInfo},
+    export_manager::{ExportJob, ExportScope},
+    extensions::{AnyhowErrorToStringChain, AppHandleExt, PathIsImg},
+    pdf_writer::PdfWriter,
+    types::{ChapterInfo, Comic, ComicInfo, PdfImageEncoding},
+    utils,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
 pub enum ExportArchive {
     Cbz,
     Pdf,
@@ -57,23 +65,27 @@ impl Drop for CbzErrorEventGuard {
 #[allow(clippy::cast_possible_wrap)]
 #[allow(clippy::cast_possible_truncation)]
 #[allow(clippy::too_many_lines)]
-pub fn cbz(app: &AppHandle, comic: &Comic) -> anyhow::Result<()> {
-    let downloaded_chapter_infos = comic
-        .chapter_infos
-        .iter()
-        .filter(|chapter_info| chapter_info.is_downloaded.unwrap_or(false))
+pub fn cbz(app: &AppHandle, job: &ExportJob) -> anyhow::Result<()> {
+    let comic = job.comic.as_ref();
+    // 图片已删除的章节之前已经导出过了，跳过，以免用空章节覆盖之前导出的cbz
+    let downloaded_chapter_infos = get_downloaded_chapter_infos(job)?
+        .into_iter()
+        .filter(|chapter_info| !chapter_info.images_deleted)
         .collect::<Vec<_>>();
     // 生成格式化的xml
     let cfg = yaserde::ser::Config {
         perform_indent: true,
         ..Default::default()
     };
-    let event_uuid = uuid::Uuid::new_v4().to_string();
+    // 用导出任务的ID作为事件的uuid，方便前端将事件与导出任务对应起来
+    let event_uuid = job.id.clone();
+    let total = downloaded_chapter_infos.len() as u32;
+    job.total.store(total, Ordering::Relaxed);
     // 发送开始导出cbz事件
     let _ = ExportCbzEvent::Start {
         uuid: event_uuid.clone(),
         comic_title: comic.name.clone(),
-        total: downloaded_chapter_infos.len() as u32,
+        total,
     }
     .emit(app);
     // 如果success为false，drop时发送Error事件
@@ -82,14 +94,10 @@ pub fn cbz(app: &AppHandle, comic: &Comic) -> anyhow::Result<()> {
         app: app.clone(),
         success: false,
     };
-    // 用来记录导出进度
-    let current = Arc::new(AtomicU32::new(0));
-
-    let extension = ExportArchive::Cbz.extension();
-    let comic_export_dir = comic
-        .get_comic_export_dir(app)
-        .context("获取导出目录失败")?;
-    let chapter_export_dir = comic_export_dir.join(extension);
+
+    let chapter_export_dir =
+        get_chapter_export_dir(app, comic, &downloaded_chapter_infos, ExportArchive::Cbz)
+            .context("获取导出目录失败")?;
     // 保证导出目录存在
     std::fs::create_dir_all(&chapter_export_dir)
         .context(format!("创建目录`{}`失败", chapter_export_dir.display()))?;
@@ -103,6 +111,8 @@ pub fn cbz(app: &AppHandle, comic: &Comic) -> anyhow::Result<()> {
     // 并发处理
     let downloaded_chapter_infos = downloaded_chapter_infos.into_par_iter();
     downloaded_chapter_infos.try_for_each(|chapter_info| -> anyhow::Result<()> {
+        // 每处理一个章节前检查导出任务是否被暂停或取消
+        job.checkpoint()?;
         let chapter_title = chapter_info.chapter_title.clone();
         // 生成ComicInfo
         let comic_info = ComicInfo::from(comic, chapter_info);
@@ -112,10 +122,9 @@ pub fn cbz(app: &AppHandle, comic: &Comic) -> anyhow::Result<()> {
                 anyhow!("章节`{chapter_title}`序列化`ComicInfo.xml`失败: {err_msg}")
             })?;
         // 创建cbz文件
-        let chapter_download_dir_name = &chapter_info
-            .get_chapter_download_dir_name()
-            .context(format!("章节`{chapter_title}`获取章节下载目录名失败"))?;
-        let save_path = chapter_export_dir.join(format!("{chapter_download_dir_name}.{extension}"));
+        let save_path = get_chapter_export_path(app, comic, chapter_info, ExportArchive::Cbz)
+            .context(format!("章节`{chapter_title}`获取导出路径失败"))?;
+        create_parent_dir(&save_path)?;
         let zip_file = std::fs::File::create(&save_path).context(format!(
             "章节`{chapter_title}`创建文件`{}`失败",
             save_path.display()
@@ -132,40 +141,64 @@ pub fn cbz(app: &AppHandle, comic: &Comic) -> anyhow::Result<()> {
             .write_all(comic_info_xml.as_bytes())
             .context(format!("章节`{chapter_title}`写入`ComicInfo.xml`失败"))?;
 
-        let chapter_download_dir = chapter_info.chapter_download_dir.as_ref().context(format!(
-            "章节`{chapter_title}`的`chapter_download_dir`字段为`None`"
-        ))?;
-        // 遍历下载目录，将文件写入cbz
-        let image_entries = std::fs::read_dir(chapter_download_dir)
-            .context(format!(
-                "章节`{chapter_title}`读取目录`{}`失败",
-                chapter_download_dir.display()
-            ))?
-            .filter_map(Result::ok);
-        for image_entry in image_entries {
-            let image_path = image_entry.path();
-            if !image_path.is_img() {
-                continue;
+        // 以cbz形式保存的章节，直接把其中的图片原样拷贝到导出的cbz
+        if chapter_info.is_archived() {
+            let archive_path = chapter_info
+                .get_chapter_archive_path()
+                .context(format!("章节`{chapter_title}`获取cbz路径失败"))?;
+            let mut zip_archive = open_zip_archive(&archive_path)
+                .context(format!("章节`{chapter_title}`打开cbz失败"))?;
+            for i in 0..zip_archive.len() {
+                let file = zip_archive.by_index_raw(i).context(format!(
+                    "章节`{chapter_title}`读取`{}`中的第{i}项失败",
+                    archive_path.display()
+                ))?;
+                if !Path::new(file.name()).is_img() {
+                    continue;
+                }
+                let filename = file.name().to_string();
+                zip_writer.raw_copy_file(file).context(format!(
+                    "章节`{chapter_title}`将`{}`中的`{filename}`写入`{}`失败",
+                    archive_path.display(),
+                    save_path.display()
+                ))?;
             }
-
-            let filename = match image_path.file_name() {
-                Some(name) => name.to_string_lossy(),
-                None => continue,
-            };
-            // 将文件写入cbz
-            zip_writer
-                .start_file(&filename, SimpleFileOptions::default())
+        } else {
+            let chapter_download_dir = chapter_info.chapter_download_dir.as_ref().context(
+                format!("章节`{chapter_title}`的`chapter_download_dir`字段为`None`"),
+            )?;
+            // 遍历下载目录，将文件写入cbz
+            let image_entries = std::fs::read_dir(chapter_download_dir)
                 .context(format!(
-                    "章节`{chapter_title}`在`{}`创建`{filename}`失败",
+                    "章节`{chapter_title}`读取目录`{}`失败",
+                    chapter_download_dir.display()
+                ))?
+                .filter_map(Result::ok);
+            for image_entry in image_entries {
+                let image_path = image_entry.path();
+                if !image_path.is_img() {
+                    continue;
+                }
+
+                let filename = match image_path.file_name() {
+                    Some(name) => name.to_string_lossy(),
+                    None => continue,
+                };
+                // 将文件原样写入cbz，GIF和动态WebP的动画会被保留
+                zip_writer
+                    .start_file(&filename, SimpleFileOptions::default())
+                    .context(format!(
+                        "章节`{chapter_title}`在`{}`创建`{filename}`失败",
+                        save_path.display()
+                    ))?;
+                let mut file = std::fs::File::open(&image_path)
+                    .context(format!("打开`{}`失败", image_path.display()))?;
+                std::io::copy(&mut file, &mut zip_writer).context(format!(
+                    "章节`{chapter_title}`将`{}`写入`{}`失败",
+                    image_path.display(),
                     save_path.display()
                 ))?;
-            let mut file = std::fs::File::open(&image_path)
-                .context(format!("打开`{}`失败", image_path.display()))?;
-            std::io::copy(&mut file, &mut zip_writer).context(format!(
-                "章节`{chapter_title}`将`{}`写入`{}`失败",
-                image_path.display(),
-                save_path.display()
-            ))?;
+            }
         }
 
         zip_writer.finish().context(format!(
@@ -173,7 +206,7 @@ pub fn cbz(app: &AppHandle, comic: &Comic) -> anyhow::Result<()> {
             save_path.display()
         ))?;
         // 更新导出cbz的进度
-        let current = current.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+        let current = job.current.fetch_add(1, Ordering::Relaxed) + 1;
         // 发送导出cbz进度事件
         let _ = ExportCbzEvent::Progress {
             uuid: event_uuid.clone(),
@@ -194,6 +227,155 @@ pub fn cbz(app: &AppHandle, comic: &Comic) -> anyhow::Result<()> {
     Ok(())
 }
 
+#[derive(Default, Debug, Clone, Serialize)]
+struct ChapterExportFmtParams {
+    comic_id: i64,
+    comic_title: String,
+    author: String,
+    chapter_id: i64,
+    chapter_title: String,
+    order: i64,
+    format: String,
+    favorite_folder: String,
+}
+
+#[derive(Default, Debug, Clone, Serialize)]
+struct ComicExportFmtParams {
+    comic_id: i64,
+    comic_title: String,
+    author: String,
+    format: String,
+    favorite_folder: String,
+}
+
+/// 章节导出路径的文件名中至少要有其中一个字段，否则所有章节都会导出到同一个文件
+const CHAPTER_DISTINCT_FIELDS: [&str; 3] = ["chapter_title", "order", "chapter_id"];
+
+/// 检查`export_chapter_fmt`和`export_comic_fmt`能否正常格式化，
+/// 以及`export_chapter_fmt`的最后一层是否能区分不同章节
+pub fn validate_export_fmts(
+    export_chapter_fmt: &str,
+    export_comic_fmt: &str,
+) -> anyhow::Result<()> {
+    utils::fmt_path_parts(export_chapter_fmt, &ChapterExportFmtParams::default())
+        .context(format!("章节导出路径格式`{export_chapter_fmt}`无效"))?;
+    utils::fmt_path_parts(export_comic_fmt, &ComicExportFmtParams::default())
+        .context(format!("整本导出路径格式`{export_comic_fmt}`无效"))?;
+
+    let filename_fmt = export_chapter_fmt
+        .rsplit('/')
+        .find(|part| !part.trim().is_empty())
+        .unwrap_or_default();
+    // strfmt的字段可以带格式说明，例如`{order:0>3}`
+    let has_distinct_field = CHAPTER_DISTINCT_FIELDS.iter().any(|field| {
+        filename_fmt.contains(&format!("{{{field}}}"))
+            || filename_fmt.contains(&format!("{{{field}:"))
+    });
+    if !has_distinct_field {
+        let fields = CHAPTER_DISTINCT_FIELDS
+            .map(|field| format!("{{{field}}}"))
+            .join("、");
+        return Err(anyhow!(
+            "章节导出路径格式`{export_chapter_fmt}`的最后一层(文件名)中至少要有{fields}其中之一，否则所有章节都会导出到同一个文件"
+        ));
+    }
+
+    Ok(())
+}
+
+/// 根据配置中的`export_chapter_fmt`获取章节的导出路径
+fn get_chapter_export_path(
+    app: &AppHandle,
+    comic: &Comic,
+    chapter_info: &ChapterInfo,
+    archive: ExportArchive,
+) -> anyhow::Result<PathBuf> {
+    let (export_dir, export_chapter_fmt) = {
+        let config = app.get_config();
+        let config = config.read();
+        (config.export_dir.clone(), config.export_chapter_fmt.clone())
+    };
+    let fmt_params = ChapterExportFmtParams {
+        comic_id: comic.id,
+        comic_title: comic.name.clone(),
+        author: comic.author.join(", "),
+        chapter_id: chapter_info.chapter_id,
+        chapter_title: chapter_info.chapter_title.clone(),
+        order: chapter_info.order,
+        format: archive.extension().to_string(),
+        favorite_folder: comic.favorite_folder.clone().unwrap_or_default(),
+    };
+    get_export_path_by_fmt(export_dir, &export_chapter_fmt, &fmt_params, archive)
+}
+
+/// 根据配置中的`export_comic_fmt`获取整本漫画(例如合并后的PDF)的导出路径
+fn get_comic_export_path(
+    app: &AppHandle,
+    comic: &Comic,
+    archive: ExportArchive,
+) -> anyhow::Result<PathBuf> {
+    let (export_dir, export_comic_fmt) = {
+        let config = app.get_config();
+        let config = config.read();
+        (config.export_dir.clone(), config.export_comic_fmt.clone())
+    };
+    let fmt_params = ComicExportFmtParams {
+        comic_id: comic.id,
+        comic_title: comic.name.clone(),
+        author: comic.author.join(", "),
+        format: archive.extension().to_string(),
+        favorite_folder: comic.favorite_folder.clone().unwrap_or_default(),
+    };
+    get_export_path_by_fmt(export_dir, &export_comic_fmt, &fmt_params, archive)
+}
+
+/// 用`fmt_params`格式化`fmt`，最后一层作为文件名，前面的层作为`export_dir`下的目录
+fn get_export_path_by_fmt<T: Serialize>(
+    export_dir: PathBuf,
+    fmt: &str,
+    fmt_params: &T,
+    archive: ExportArchive,
+) -> anyhow::Result<PathBuf> {
+    let mut names = utils::fmt_path_parts(fmt, fmt_params).context("格式化导出路径失败")?;
+    let Some(filename) = names.pop() else {
+        return Err(anyhow!("导出路径格式`{fmt}`格式化后为空"));
+    };
+
+    let mut export_path = export_dir;
+    for name in names {
+        export_path = export_path.join(name);
+    }
+    let extension = archive.extension();
+    Ok(export_path.join(format!("{filename}.{extension}")))
+}
+
+/// 获取章节导出文件所在的目录，用于拷贝封面和在前端打开导出目录
+///
+/// 以第一个章节的导出路径的父目录为准，没有章节时返回配置中的导出目录
+fn get_chapter_export_dir(
+    app: &AppHandle,
+    comic: &Comic,
+    chapter_infos: &[&ChapterInfo],
+    archive: ExportArchive,
+) -> anyhow::Result<PathBuf> {
+    let Some(first_chapter_info) = chapter_infos.first() else {
+        return Ok(app.get_config().read().export_dir.clone());
+    };
+    let chapter_export_path = get_chapter_export_path(app, comic, first_chapter_info, archive)?;
+    let chapter_export_dir = chapter_export_path
+        .parent()
+        .context(format!("`{}`没有父目录", chapter_export_path.display()))?;
+    Ok(chapter_export_dir.to_path_buf())
+}
+
+/// 保证`path`的父目录存在
+fn create_parent_dir(path: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).context(format!("创建目录`{}`失败", parent.display()))?;
+    }
+    Ok(())
+}
+
 fn copy_cover(comic: &Comic, chapter_export_dir: &Path) -> anyhow::Result<()> {
     let src_cover_path = comic.get_cover_path().context("获取封面路径失败")?;
     let cover_filename = src_cover_path.file_name().context("获取封面的文件名失败")?;
@@ -206,6 +388,33 @@ fn copy_cover(comic: &Comic, chapter_export_dir: &Path) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// 创建PDF时的选项
+#[derive(Debug, Clone, Copy)]
+struct PdfOptions {
+    /// 页面的宽和高(单位为pt)，为`None`时页面尺寸与图片的像素尺寸一致
+    page_size: Option<(f32, f32)>,
+    /// 非JPEG图片的编码方式
+    image_encoding: PdfImageEncoding,
+    /// 转码为JPEG时的质量
+    jpeg_quality: u8,
+}
+
+impl PdfOptions {
+    fn from_app(app: &AppHandle) -> Self {
+        let config = app.get_config();
+        let config = config.read();
+        let page_size = config.pdf_page_size.dimensions(
+            config.pdf_custom_page_width_mm,
+            config.pdf_custom_page_height_mm,
+        );
+        PdfOptions {
+            page_size,
+            image_encoding: config.pdf_image_encoding,
+            jpeg_quality: config.pdf_jpeg_quality.clamp(1, 100),
+        }
+    }
+}
+
 struct PdfCreateErrorEventGuard {
     uuid: String,
     app: AppHandle,
@@ -240,19 +449,161 @@ impl Drop for PdfMergeErrorEventGuard {
     }
 }
 
-#[allow(clippy::cast_possible_truncation)]
-pub fn pdf(app: &AppHandle, comic: &Comic) -> anyhow::Result<()> {
-    let downloaded_chapter_infos: Vec<&ChapterInfo> = comic
+pub fn pdf(app: &AppHandle, job: &ExportJob) -> anyhow::Result<()> {
+    let comic = job.comic.as_ref();
+    // 图片已删除的章节之前已经导出过了，不再创建章节PDF，合并时直接使用之前导出的章节PDF
+    let (images_deleted_chapter_infos, downloaded_chapter_infos): (Vec<_>, Vec<_>) =
+        get_downloaded_chapter_infos(job)?
+            .into_iter()
+            .partition(|chapter_info| chapter_info.images_deleted);
+    // 导出PDF时无法解码AVIF，开始导出前先检查，以免导出到一半才失败
+    ensure_no_avif_images(&downloaded_chapter_infos)?;
+
+    let options = PdfOptions::from_app(app);
+    // 每个章节和它合并时的来源
+    let mut chapters = if job.scope.creates_chapter_files() {
+        create_chapter_pdfs(app, job, &downloaded_chapter_infos, options)?
+    } else {
+        downloaded_chapter_infos
+            .iter()
+            .map(|chapter_info| {
+                let images = get_chapter_images(chapter_info)?;
+                Ok((*chapter_info, MergeSource::Images(images)))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+    };
+
+    if !job.scope.creates_merged_file() {
+        return Ok(());
+    }
+
+    for chapter_info in images_deleted_chapter_infos {
+        let chapter_title = &chapter_info.chapter_title;
+        let pdf_path = get_chapter_export_path(app, comic, chapter_info, ExportArchive::Pdf)
+            .context(format!("章节`{chapter_title}`获取导出路径失败"))?;
+        if !pdf_path.is_file() {
+            return Err(anyhow!(
+                "章节`{chapter_title}`的图片已在导出后删除，且找不到之前导出的`{}`，无法合并",
+                pdf_path.display()
+            ));
+        }
+        chapters.push((chapter_info, MergeSource::ChapterPdf(pdf_path)));
+    }
+    chapters.sort_by_key(|(chapter_info, _)| chapter_info.order);
+    let chapters: Vec<(String, MergeSource)> = chapters
+        .into_iter()
+        .map(|(chapter_info, source)| (chapter_info.chapter_title.clone(), source))
+        .collect();
+    // 合并前再检查一次导出任务是否被暂停或取消
+    job.checkpoint()?;
+    let event_uuid = uuid::Uuid::new_v4().to_string();
+    // 发送开始合并pdf事件
+    let _ = ExportPdfEvent::MergeStart {
+        uuid: event_uuid.clone(),
+        comic_title: comic.name.clone(),
+    }
+    .emit(app);
+    // 如果success为false，drop时发送MergeError事件
+    let mut merge_error_event_guard = PdfMergeErrorEventGuard {
+        uuid: event_uuid.clone(),
+        app: app.clone(),
+        success: false,
+    };
+
+    let save_path = get_comic_export_path(app, comic, ExportArchive::Pdf)
+        .context("获取合并后PDF的导出路径失败")?;
+    create_parent_dir(&save_path)?;
+    // 如果需要封面页，且封面存在，则把封面作为合并后PDF的第一页
+    let cover_first = app.get_config().read().pdf_cover_first;
+    let cover_path = if cover_first {
+        comic
+            .get_cover_path()
+            .ok()
+            .filter(|cover_path| cover_path.is_file())
+    } else {
+        None
+    };
+    let info = pdf_info(comic, &comic.name);
+    merge_pdf(
+        job,
+        &chapters,
+        cover_path.as_deref(),
+        &save_path,
+        options,
+        info,
+    )
+    .context("合并pdf失败")?;
+    // 标记为成功，后面drop时就不会发送MergeError事件
+    merge_error_event_guard.success = true;
+    // 发送合并pdf完成事件
+    let _ = ExportPdfEvent::MergeEnd {
+        uuid: event_uuid,
+        chapter_export_dir: save_path,
+    }
+    .emit(app);
+    Ok(())
+}
+
+/// 导出PDF时无法解码AVIF，如果章节中有AVIF图片，则返回错误
+fn ensure_no_avif_images(chapter_infos: &[&ChapterInfo]) -> anyhow::Result<()> {
+    for chapter_info in chapter_infos {
+        let images = get_chapter_images(chapter_info)?;
+        if images.iter().any(ChapterImage::is_avif) {
+            let chapter_title = &chapter_info.chapter_title;
+            return Err(anyhow!(
+                "章节`{chapter_title}`中有AVIF图片，导出PDF不支持AVIF，请改为导出cbz"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 导出范围内已下载的章节，按`order`排序
+///
+/// 如果只导出单个章节，而它的图片已在导出后删除，则返回错误，以免用空章节覆盖之前导出的文件
+fn get_downloaded_chapter_infos(job: &ExportJob) -> anyhow::Result<Vec<&ChapterInfo>> {
+    let mut downloaded_chapter_infos: Vec<&ChapterInfo> = job
+        .comic
         .chapter_infos
         .iter()
         .filter(|chapter_info| chapter_info.is_downloaded.unwrap_or(false))
+        .filter(|chapter_info| job.scope.includes_chapter(chapter_info.chapter_id))
         .collect();
-    let event_uuid = uuid::Uuid::new_v4().to_string();
+    downloaded_chapter_infos.sort_by_key(|chapter_info| chapter_info.order);
+
+    if let ExportScope::Chapter(_) = job.scope {
+        if let Some(chapter_info) = downloaded_chapter_infos
+            .iter()
+            .find(|chapter_info| chapter_info.images_deleted)
+        {
+            let chapter_title = &chapter_info.chapter_title;
+            return Err(anyhow!(
+                "章节`{chapter_title}`的图片已在导出后删除，无法再次导出"
+            ));
+        }
+    }
+
+    Ok(downloaded_chapter_infos)
+}
+
+/// 为`downloaded_chapter_infos`中的每个章节创建PDF，返回按顺序排列的章节和创建的章节PDF
+#[allow(clippy::cast_possible_truncation)]
+fn create_chapter_pdfs<'a>(
+    app: &AppHandle,
+    job: &ExportJob,
+    downloaded_chapter_infos: &[&'a ChapterInfo],
+    options: PdfOptions,
+) -> anyhow::Result<Vec<(&'a ChapterInfo, MergeSource)>> {
+    let comic = job.comic.as_ref();
+    // 用导出任务的ID作为事件的uuid，方便前端将事件与导出任务对应起来
+    let event_uuid = job.id.clone();
+    let total = downloaded_chapter_infos.len() as u32;
+    job.total.store(total, Ordering::Relaxed);
     // 发送开始创建pdf事件
     let _ = ExportPdfEvent::CreateStart {
         uuid: event_uuid.clone(),
         comic_title: comic.name.clone(),
-        total: downloaded_chapter_infos.len() as u32,
+        total,
     }
     .emit(app);
     // 如果success为false，drop时发送CreateError事件
@@ -261,48 +612,34 @@ pub fn pdf(app: &AppHandle, comic: &Comic) -> anyhow::Result<()> {
         app: app.clone(),
         success: false,
     };
-    // 用来记录创建pdf的进度
-    let current = Arc::new(AtomicU32::new(0));
-
-    let extension = ExportArchive::Pdf.extension();
-    let comic_export_dir = comic
-        .get_comic_export_dir(app)
-        .context("获取导出目录失败")?;
-    let chapter_export_dir = comic_export_dir.join(extension);
+
+    let chapter_export_dir =
+        get_chapter_export_dir(app, comic, downloaded_chapter_infos, ExportArchive::Pdf)
+            .context("获取导出目录失败")?;
     // 保证导出目录存在
     std::fs::create_dir_all(&chapter_export_dir)
         .context(format!("创建目录`{}`失败", chapter_export_dir.display()))?;
-    let chapter_with_pdf_path = Mutex::new(Vec::new());
+    let chapter_with_pdfs = Mutex::new(Vec::new());
     // 并发处理
     let downloaded_chapter_infos = downloaded_chapter_infos.into_par_iter();
     downloaded_chapter_infos.try_for_each(|chapter_info| -> anyhow::Result<()> {
+        // 每处理一个章节前检查导出任务是否被暂停或取消
+        job.checkpoint()?;
         let chapter_title = &chapter_info.chapter_title;
 
-        let chapter_download_dir = chapter_info.chapter_download_dir.as_ref().context(format!(
-            "章节`{chapter_title}`的`chapter_download_dir`字段为`None`"
-        ))?;
-        let mut image_paths: Vec<PathBuf> = std::fs::read_dir(chapter_download_dir)
-            .context(format!(
-                "章节`{chapter_title}`读取目录`{}`失败",
-                chapter_download_dir.display()
-            ))?
-            .filter_map(Result::ok)
-            .map(|entry| entry.path())
-            .filter(|path| path.is_common_img())
-            .collect();
-        image_paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
-
-        let chapter_download_dir_name = &chapter_info
-            .get_chapter_download_dir_name()
-            .context(format!("章节`{chapter_title}`获取章节下载目录名失败"))?;
+        let images = get_chapter_images(chapter_info)?;
         // 创建pdf
-        let save_path = chapter_export_dir.join(format!("{chapter_download_dir_name}.{extension}"));
-
-        create_pdf(image_paths, &save_path).context(format!("章节`{chapter_title}`创建pdf失败"))?;
+        let save_path = get_chapter_export_path(app, comic, chapter_info, ExportArchive::Pdf)
+            .context(format!("章节`{chapter_title}`获取导出路径失败"))?;
+        create_parent_dir(&save_path)?;
 
-        chapter_with_pdf_path.lock().push((chapter_info, save_path));
+        let info = pdf_info(comic, &format!("{} - {chapter_title}", comic.name));
+        create_pdf(&images, &save_path, options, info)
+            .context(format!("章节`{chapter_title}`创建pdf失败"))?;
+        // 记录章节PDF，合并时直接复用其中已编码的图片流
+        chapter_with_pdfs.lock().push((*chapter_info, save_path));
         // 更新创建pdf的进度
-        let current = current.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+        let current = job.current.fetch_add(1, Ordering::Relaxed) + 1;
         // 发送创建pdf进度事件
         let _ = ExportPdfEvent::CreateProgress {
             uuid: event_uuid.clone(),
@@ -312,11 +649,11 @@ pub fn pdf(app: &AppHandle, comic: &Comic) -> anyhow::Result<()> {
         Ok(())
     })?;
 
-    let mut chapter_with_pdf_path = std::mem::take(&mut *chapter_with_pdf_path.lock());
-    chapter_with_pdf_path.sort_by(|(a, _), (b, _)| a.order.cmp(&b.order));
-    let chapter_pdf_paths: Vec<PathBuf> = chapter_with_pdf_path
+    let mut chapter_with_pdfs = std::mem::take(&mut *chapter_with_pdfs.lock());
+    chapter_with_pdfs.sort_by_key(|(chapter_info, _)| chapter_info.order);
+    let chapters: Vec<(&ChapterInfo, MergeSource)> = chapter_with_pdfs
         .into_iter()
-        .map(|(_, pdf_path)| pdf_path)
+        .map(|(chapter_info, pdf_path)| (chapter_info, MergeSource::ChapterPdf(pdf_path)))
         .collect();
 
     // 标记为成功，后面drop时就不会发送CreateError事件
@@ -328,111 +665,310 @@ pub fn pdf(app: &AppHandle, comic: &Comic) -> anyhow::Result<()> {
     }
     .emit(app);
 
-    let event_uuid = uuid::Uuid::new_v4().to_string();
-    // 发送开始合并pdf事件
-    let _ = ExportPdfEvent::MergeStart {
-        uuid: event_uuid.clone(),
-        comic_title: comic.name.clone(),
+    Ok(chapters)
+}
+
+/// 章节中的一张图片，可能是章节目录中的文件，也可能是章节cbz中的一项
+#[derive(Debug, Clone)]
+enum ChapterImage {
+    File(PathBuf),
+    Archived { archive_path: PathBuf, name: String },
+}
+
+impl ChapterImage {
+    fn is_avif(&self) -> bool {
+        match self {
+            ChapterImage::File(path) => path.is_avif(),
+            ChapterImage::Archived { name, .. } => Path::new(name).is_avif(),
+        }
     }
-    .emit(app);
-    // 如果success为false，drop时发送MergeError事件
-    let mut merge_error_event_guard = PdfMergeErrorEventGuard {
-        uuid: event_uuid.clone(),
-        app: app.clone(),
-        success: false,
-    };
+}
 
-    let comic_download_dir_name = &comic
-        .get_comic_download_dir_name()
-        .context("获取漫画下载目录名失败")?;
-    let save_path = comic_export_dir.join(format!("{comic_download_dir_name}.{extension}"));
-    merge_pdf(chapter_pdf_paths, &save_path).context("合并pdf失败")?;
-    // 标记为成功，后面drop时就不会发送MergeError事件
-    merge_error_event_guard.success = true;
-    // 发送合并pdf完成事件
-    let _ = ExportPdfEvent::MergeEnd {
-        uuid: event_uuid,
-        chapter_export_dir: save_path,
+/// 读取章节图片，连续读取同一个cbz中的图片时只打开一次cbz
+#[derive(Default)]
+struct ChapterImageReader {
+    zip_archive: Option<(PathBuf, ZipArchive<std::fs::File>)>,
+}
+
+impl ChapterImageReader {
+    fn read(&mut self, image: &ChapterImage) -> anyhow::Result<Vec<u8>> {
+        match image {
+            ChapterImage::File(path) => read_image_to_buffer(path),
+            ChapterImage::Archived { archive_path, name } => {
+                let opened = matches!(&self.zip_archive, Some((path, _)) if path == archive_path);
+                if !opened {
+                    let zip_archive = open_zip_archive(archive_path)?;
+                    self.zip_archive = Some((archive_path.clone(), zip_archive));
+                }
+                let (_, zip_archive) = self
+                    .zip_archive
+                    .as_mut()
+                    .context(format!("`{}`没有被打开", archive_path.display()))?;
+                let mut file = zip_archive
+                    .by_name(name)
+                    .context(format!("`{}`中没有`{name}`", archive_path.display()))?;
+                let mut buffer = vec![];
+                file.read_to_end(&mut buffer)
+                    .context(format!("读取`{image}`失败"))?;
+                Ok(buffer)
+            }
+        }
     }
-    .emit(app);
+}
+
+impl std::fmt::Display for ChapterImage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChapterImage::File(path) => write!(f, "{}", path.display()),
+            ChapterImage::Archived { archive_path, name } => {
+                write!(f, "{}/{name}", archive_path.display())
+            }
+        }
+    }
+}
+
+fn open_zip_archive(archive_path: &Path) -> anyhow::Result<ZipArchive<std::fs::File>> {
+    let zip_file = std::fs::File::open(archive_path)
+        .context(format!("打开`{}`失败", archive_path.display()))?;
+    let zip_archive =
+        ZipArchive::new(zip_file).context(format!("读取`{}`失败", archive_path.display()))?;
+    Ok(zip_archive)
+}
+
+/// 获取章节的所有图片，按文件名排序
+///
+/// 以cbz形式保存的章节从cbz中读取，否则从章节下载目录中读取
+fn get_chapter_images(chapter_info: &ChapterInfo) -> anyhow::Result<Vec<ChapterImage>> {
+    let chapter_title = &chapter_info.chapter_title;
+
+    if chapter_info.is_archived() {
+        let archive_path = chapter_info
+            .get_chapter_archive_path()
+            .context(format!("章节`{chapter_title}`获取cbz路径失败"))?;
+        let zip_archive =
+            open_zip_archive(&archive_path).context(format!("章节`{chapter_title}`打开cbz失败"))?;
+        let mut names: Vec<String> = zip_archive
+            .file_names()
+            .filter(|name| Path::new(name).is_img())
+            .map(ToString::to_string)
+            .collect();
+        names.sort();
+        let images = names
+            .into_iter()
+            .map(|name| ChapterImage::Archived {
+                archive_path: archive_path.clone(),
+                name,
+            })
+            .collect();
+        return Ok(images);
+    }
+
+    let chapter_download_dir = chapter_info.chapter_download_dir.as_ref().context(format!(
+        "章节`{chapter_title}`的`chapter_download_dir`字段为`None`"
+    ))?;
+    let mut image_paths: Vec<PathBuf> = std::fs::read_dir(chapter_download_dir)
+        .context(format!(
+            "章节`{chapter_title}`读取目录`{}`失败",
+            chapter_download_dir.display()
+        ))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_img())
+        .collect();
+    image_paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+
+    Ok(image_paths.into_iter().map(ChapterImage::File).collect())
+}
+
+/// 用`images`中的图片按顺序创建PDF，保存到`save_path`中
+fn create_pdf(
+    images: &[ChapterImage],
+    save_path: &Path,
+    options: PdfOptions,
+    info: Dictionary,
+) -> anyhow::Result<()> {
+    let mut writer = PdfWriter::create(save_path)?;
+
+    let mut reader = ChapterImageReader::default();
+    for image in images {
+        add_image_page(&mut writer, &mut reader, image, options)?;
+    }
+
+    writer
+        .finish(info)
+        .context(format!("保存`{}`失败", save_path.display()))?;
+
     Ok(())
 }
 
-/// 用`image_paths`中的图片按顺序创建PDF，保存到`save_path`中
-#[allow(clippy::similar_names)]
-#[allow(clippy::cast_possible_truncation)]
-fn create_pdf(image_paths: Vec<PathBuf>, save_path: &Path) -> anyhow::Result<()> {
-    let mut doc = Document::with_version("1.5");
-    let pages_id = doc.new_object_id();
-    let mut page_ids = vec![];
+/// 在`writer`中创建一个显示`image`的页面，返回页面的 ID
+fn add_image_page(
+    writer: &mut PdfWriter,
+    reader: &mut ChapterImageReader,
+    image: &ChapterImage,
+    options: PdfOptions,
+) -> anyhow::Result<ObjectId> {
+    let buffer = reader
+        .read(image)
+        .context(format!("将`{image}`读取到buffer失败"))?;
+    let (image_stream, dimensions) =
+        create_image_stream(buffer, options).context(format!("创建`{image}`的图片流失败"))?;
+    let page_id = writer
+        .add_image_page(image_stream, dimensions, options.page_size)
+        .context(format!("将`{image}`写入PDF失败"))?;
+    Ok(page_id)
+}
 
-    for image_path in image_paths {
-        if !image_path.is_file() {
-            continue;
-        }
+/// 用图片数据创建PDF的图片流，返回图片流和图片的像素尺寸
+///
+/// - JPEG图片直接以`DCTDecode`嵌入，不重新编码
+/// - 其他格式(PNG/WebP/GIF)解码后按`options.image_encoding`转码为JPEG或Flate压缩的原始像素
+/// - GIF和动态WebP只取第一帧，透明部分会被白色填充
+fn create_image_stream(
+    buffer: Vec<u8>,
+    options: PdfOptions,
+) -> anyhow::Result<(Stream, (u32, u32))> {
+    let format = image::guess_format(&buffer).context("无法识别图片格式")?;
 
-        let buffer = read_image_to_buffer(&image_path)
-            .context(format!("将`{}`读取到buffer失败", image_path.display()))?;
-        let (width, height) = image::image_dimensions(&image_path)
-            .context(format!("获取`{}`的尺寸失败", image_path.display()))?;
-        let image_stream = lopdf::xobject::image_from(buffer)
-            .context(format!("创建`{}`的图片流失败", image_path.display()))?;
-        // 将图片流添加到doc中
-        let img_id = doc.add_object(image_stream);
-        // 图片的名称，用于 Do 操作在页面上显示图片
-        let img_name = format!("X{}", img_id.0);
-        // 用于设置图片在页面上的位置和大小
-        let cm_operation = Operation::new(
-            "cm",
-            vec![
-                width.into(),
-                0.into(),
-                0.into(),
-                height.into(),
-                0.into(),
-                0.into(),
-            ],
-        );
-        // 用于显示图片
-        let do_operation = Operation::new("Do", vec![Object::Name(img_name.as_bytes().to_vec())]);
-        // 创建页面，设置图片的位置和大小，然后显示图片
-        // 因为是从零开始创建PDF，所以没必要用 q 和 Q 操作保存和恢复图形状态
-        let content = Content {
-            operations: vec![cm_operation, do_operation],
+    if format == ImageFormat::Jpeg {
+        let decoder = JpegDecoder::new(Cursor::new(&buffer)).context("解析JPEG失败")?;
+        let dimensions = decoder.dimensions();
+        // 解码器会把CMYK/YCCK的JPEG报告为Rgb8，所以要以SOF段中的分量数为准
+        let color_space = match (jpeg_component_count(&buffer), decoder.color_type()) {
+            (Some(1), ColorType::L8) => Some("DeviceGray"),
+            (Some(3), ColorType::Rgb8) => Some("DeviceRGB"),
+            _ => None,
         };
-        let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode()?));
-        let page_id = doc.add_object(dictionary! {
-            "Type" => "Page",
-            "Parent" => pages_id,
-            "Contents" => content_id,
-            "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
-        });
-        // 将图片以 XObject 的形式添加到文档中
-        // Do 操作只能引用 XObject(所以前面定义的 Do 操作的参数是 img_name, 而不是 img_id)
-        doc.add_xobject(page_id, img_name.as_bytes(), img_id)?;
-        // 记录新创建的页面的 ID
-        page_ids.push(page_id);
-    }
-    // 将"Pages"添加到doc中
-    let pages_dict = dictionary! {
-        "Type" => "Pages",
-        "Count" => page_ids.len() as u32,
-        "Kids" => page_ids.into_iter().map(Object::Reference).collect::<Vec<_>>(),
+        // 灰度和RGB的JPEG可以直接嵌入，其他的(例如CMYK/YCCK)需要转码
+        if let Some(color_space) = color_space {
+            let (width, height) = dimensions;
+            let dict = dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => width,
+                "Height" => height,
+                "ColorSpace" => color_space,
+                "BitsPerComponent" => 8,
+                "Filter" => "DCTDecode",
+            };
+            return Ok((Stream::new(dict, buffer), dimensions));
+        }
+    }
+    // 解码图片，如果是GIF或动态WebP则只会解码第一帧
+    let img = image::load_from_memory_with_format(&buffer, format).context("解码图片失败")?;
+    drop(buffer);
+    let dimensions = img.dimensions();
+    let (width, height) = dimensions;
+    // PDF的DeviceGray和DeviceRGB都不支持透明通道，所以先把透明部分用白色填充
+    let is_gray = !img.color().has_color();
+    let img = flatten_alpha(img, is_gray);
+    let color_space = if is_gray { "DeviceGray" } else { "DeviceRGB" };
+
+    let mut dict = dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Image",
+        "Width" => width,
+        "Height" => height,
+        "ColorSpace" => color_space,
+        "BitsPerComponent" => 8,
+    };
+    let content = match options.image_encoding {
+        PdfImageEncoding::Jpeg => {
+            let mut jpeg_data = vec![];
+            let encoder = JpegEncoder::new_with_quality(&mut jpeg_data, options.jpeg_quality);
+            img.write_with_encoder(encoder)
+                .context("将图片转码为JPEG失败")?;
+            dict.set("Filter", "DCTDecode");
+            jpeg_data
+        }
+        PdfImageEncoding::Flate => {
+            let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::default());
+            encoder
+                .write_all(img.as_bytes())
+                .context("用Flate压缩图片失败")?;
+            dict.set("Filter", "FlateDecode");
+            encoder.finish().context("用Flate压缩图片失败")?
+        }
     };
-    doc.objects.insert(pages_id, Object::Dictionary(pages_dict));
-    // 新建一个"Catalog"对象，将"Pages"对象添加到"Catalog"对象中，然后将"Catalog"对象添加到doc中
-    let catalog_id = doc.add_object(dictionary! {
-        "Type" => "Catalog",
-        "Pages" => pages_id,
-    });
-    doc.trailer.set("Root", catalog_id);
 
-    doc.compress();
+    Ok((Stream::new(dict, content), dimensions))
+}
 
-    doc.save(save_path)
-        .context(format!("保存`{}`失败", save_path.display()))?;
+/// 从JPEG的SOF段读取颜色分量数，找不到SOF段时返回`None`
+fn jpeg_component_count(buffer: &[u8]) -> Option<u8> {
+    // 跳过SOI
+    let mut pos = 2;
+    while pos + 4 <= buffer.len() {
+        if buffer[pos] != 0xFF {
+            return None;
+        }
+        let marker = buffer[pos + 1];
+        // 标记前可以有任意个0xFF填充字节
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        // 这些标记没有长度字段
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            pos += 2;
+            continue;
+        }
+        // SOFn为0xC0~0xCF，但0xC4(DHT)、0xC8(JPG)、0xCC(DAC)不是
+        let is_sof = (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+        if is_sof {
+            // 标记(2) + 长度(2) + 精度(1) + 高(2) + 宽(2)之后就是分量数
+            return buffer.get(pos + 9).copied();
+        }
+        // SOS之后是图像数据，SOF一定在SOS之前
+        if marker == 0xDA {
+            return None;
+        }
+        let len = u16::from_be_bytes([buffer[pos + 2], buffer[pos + 3]]);
+        pos += 2 + usize::from(len);
+    }
+    None
+}
 
-    Ok(())
+/// 将`img`转换为8位的灰度图或RGB图，透明部分用白色填充
+#[allow(clippy::cast_possible_truncation)]
+fn flatten_alpha(img: DynamicImage, is_gray: bool) -> DynamicImage {
+    // 将前景色`c`以`alpha`的不透明度叠加到白色背景上
+    let blend = |c: u8, alpha: u8| -> u8 {
+        let (c, alpha) = (u32::from(c), u32::from(alpha));
+        ((c * alpha + 255 * (255 - alpha) + 127) / 255) as u8
+    };
+
+    match (is_gray, img.color().has_alpha()) {
+        (true, false) => DynamicImage::ImageLuma8(img.into_luma8()),
+        (false, false) => DynamicImage::ImageRgb8(img.into_rgb8()),
+        (true, true) => {
+            let luma_alpha = img.into_luma_alpha8();
+            let luma = GrayImage::from_fn(luma_alpha.width(), luma_alpha.height(), |x, y| {
+                let [l, a] = luma_alpha.get_pixel(x, y).0;
+                Luma([blend(l, a)])
+            });
+            DynamicImage::ImageLuma8(luma)
+        }
+        (false, true) => {
+            let rgba = img.into_rgba8();
+            let rgb = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+                let [r, g, b, a] = rgba.get_pixel(x, y).0;
+                Rgb([blend(r, a), blend(g, a), blend(b, a)])
+            });
+            DynamicImage::ImageRgb8(rgb)
+        }
+    }
+}
+
+/// 用`comic`的信息生成PDF的文档信息字典
+fn pdf_info(comic: &Comic, title: &str) -> Dictionary {
+    dictionary! {
+        "Title" => lopdf::text_string(title),
+        "Author" => lopdf::text_string(&comic.author.join(", ")),
+        "Subject" => lopdf::text_string(&comic.description),
+        "Keywords" => lopdf::text_string(&comic.tags.join(", ")),
+        "Creator" => lopdf::text_string("jmcomic-downloader"),
+    }
 }
 
 /// 读取`image_path`中的图片数据到buffer中
@@ -447,82 +983,121 @@ fn read_image_to_buffer(image_path: &Path) -> anyhow::Result<Vec<u8>> {
     Ok(buffer)
 }
 
-/// 将`chapter_pdf_paths`中的PDF按顺序合并成一个，保存到`save_path`中
-#[allow(clippy::cast_possible_truncation)]
-fn merge_pdf(chapter_pdf_paths: Vec<PathBuf>, save_path: &Path) -> anyhow::Result<()> {
-    let mut doc = Document::with_version("1.5");
-    let mut doc_page_ids = vec![];
-    let mut doc_objects = BTreeMap::new();
-
-    for chapter_pdf_path in chapter_pdf_paths {
-        let mut chapter_doc = Document::load(&chapter_pdf_path)
-            .context(format!("加载`{}`失败", chapter_pdf_path.display()))?;
-        // 重新编号这个章节PDF的对象，避免与doc的对象编号冲突
-        chapter_doc.renumber_objects_with(doc.max_id);
-        doc.max_id = chapter_doc.max_id + 1;
-        // 获取这个章节PDF中的所有页面，并给第一个页面添加书签
-        let mut chapter_page_ids = vec![];
-        for (page_num, object_id) in chapter_doc.get_pages() {
-            // 第一个页面需要添加书签
-            if page_num == 1 {
-                let chapter_title = chapter_pdf_path
-                    .file_stem()
-                    .context(format!("获取`{}`的文件名失败", chapter_pdf_path.display()))?
-                    .to_string_lossy()
-                    .to_string();
-                let bookmark = Bookmark::new(chapter_title, [0.0, 0.0, 1.0], 0, object_id);
-                doc.add_bookmark(bookmark, None);
-            }
-            chapter_page_ids.push(object_id);
-        }
+/// 合并PDF时章节页面的来源
+enum MergeSource {
+    /// 已创建的章节PDF，直接复用其中已编码的图片流
+    ChapterPdf(PathBuf),
+    /// 没有创建章节PDF，需要从图片创建页面
+    Images(Vec<ChapterImage>),
+}
 
-        doc_page_ids.extend(chapter_page_ids);
-        doc_objects.extend(chapter_doc.objects);
+/// 用每个章节的页面创建合并后的PDF，保存到`save_path`中
+///
+/// 一次只加载一个章节的PDF或一张图片，边读边写入，所以峰值内存与漫画的章节数无关
+///
+/// - `chapters`为按顺序排列的章节标题和对应的页面来源
+/// - 如果`cover_path`不为`None`，则把封面作为第一页
+fn merge_pdf(
+    job: &ExportJob,
+    chapters: &[(String, MergeSource)],
+    cover_path: Option<&Path>,
+    save_path: &Path,
+    options: PdfOptions,
+    info: Dictionary,
+) -> anyhow::Result<()> {
+    let mut writer = PdfWriter::create(save_path)?;
+
+    if let Some(cover_path) = cover_path {
+        let cover = ChapterImage::File(cover_path.to_path_buf());
+        let mut reader = ChapterImageReader::default();
+        add_image_page(&mut writer, &mut reader, &cover, options)
+            .context(format!("创建封面页`{}`失败", cover_path.display()))?;
+        writer.add_page_label(
+            0,
+            dictionary! {
+                "P" => lopdf::text_string("封面"),
+            },
+        );
     }
-    // 在doc中新建一个"Pages"对象，将所有章节的页面添加到这个"Pages"对象中
-    let pages_id = doc.add_object(dictionary! {
-        "Type" => "Pages",
-        "Count" => doc_page_ids.len() as u32,
-        "Kids" => doc_page_ids.into_iter().map(Object::Reference).collect::<Vec<_>>(),
-    });
 
-    for (object_id, mut object) in doc_objects {
-        match object.type_name().unwrap_or(b"") {
-            b"Page" => {
-                if let Ok(page_dict) = object.as_dict_mut() {
-                    // 将页面对象的"Parent"字段设置为新建的"Pages"对象，这样这个页面就成为了"Pages"对象的子页面
-                    page_dict.set("Parent", pages_id);
-                    doc.objects.insert(object_id, object);
-                };
+    for (chapter_title, source) in chapters {
+        // 每合并一个章节前检查导出任务是否被暂停或取消
+        job.checkpoint()?;
+        let first_page_index = writer.page_count();
+        let first_page_id = match source {
+            MergeSource::ChapterPdf(pdf_path) => {
+                copy_chapter_pdf_pages(&mut writer, pdf_path, options).context(format!(
+                    "复用章节`{chapter_title}`的PDF`{}`失败",
+                    pdf_path.display()
+                ))?
             }
-            // 忽略这些对象
-            b"Catalog" | b"Pages" | b"Outlines" | b"Outline" => {}
-            // 将所有其他对象添加到doc中
-            _ => {
-                doc.objects.insert(object_id, object);
+            MergeSource::Images(images) => {
+                let mut first_page_id = None;
+                let mut reader = ChapterImageReader::default();
+                for image in images {
+                    let page_id = add_image_page(&mut writer, &mut reader, image, options)?;
+                    first_page_id.get_or_insert(page_id);
+                }
+                first_page_id
             }
-        }
-    }
-    // 新建一个"Catalog"对象，将"Pages"对象添加到"Catalog"对象中，然后将"Catalog"对象添加到doc中
-    let catalog_id = doc.add_object(dictionary! {
-        "Type" => "Catalog",
-        "Pages" => pages_id,
-    });
-    doc.trailer.set("Root", catalog_id);
-    // 如果有书签没有关联到具体页面，将这些书签指向第一个页面
-    doc.adjust_zero_pages();
-    // 将书签添加到doc中
-    if let Some(outline_id) = doc.build_outline() {
-        if let Ok(Object::Dictionary(catalog_dict)) = doc.get_object_mut(catalog_id) {
-            catalog_dict.set("Outlines", Object::Reference(outline_id));
-        }
+        };
+        // 给章节的第一个页面添加书签和页码标签，每个章节的页码从1开始，并以章节标题作为前缀
+        let Some(first_page_id) = first_page_id else {
+            continue;
+        };
+        writer.add_bookmark(chapter_title.clone(), first_page_id);
+        writer.add_page_label(
+            first_page_index,
+            dictionary! {
+                "S" => "D",
+                "P" => lopdf::text_string(&format!("{chapter_title} - ")),
+            },
+        );
     }
-    // 重新编号doc的对象
-    doc.renumber_objects();
-
-    doc.compress();
 
-    doc.save(save_path)
+    writer
+        .finish(info)
         .context(format!("保存`{}`失败", save_path.display()))?;
     Ok(())
 }
+
+/// 把章节PDF中每一页的图片流原样写入`writer`，不重新解码和编码图片，返回第一个页面的 ID
+///
+/// 一次只加载一个章节PDF，所以峰值内存只与最大的章节有关
+fn copy_chapter_pdf_pages(
+    writer: &mut PdfWriter,
+    pdf_path: &Path,
+    options: PdfOptions,
+) -> anyhow::Result<Option<ObjectId>> {
+    let doc = Document::load(pdf_path).context(format!("加载`{}`失败", pdf_path.display()))?;
+
+    let mut first_page_id = None;
+    for (page_number, page_id) in doc.get_pages() {
+        let image_stream = get_page_image_stream(&doc, page_id)
+            .context(format!("获取第{page_number}页的图片流失败"))?;
+        let width = image_stream.dict.get(b"Width").and_then(Object::as_i64)?;
+        let height = image_stream.dict.get(b"Height").and_then(Object::as_i64)?;
+        let dimensions = (u32::try_from(width)?, u32::try_from(height)?);
+        // 用`Stream::new`重新创建，保证`Length`与内容一致
+        let image_stream = Stream::new(image_stream.dict.clone(), image_stream.content.clone());
+        let page_id = writer
+            .add_image_page(image_stream, dimensions, options.page_size)
+            .context(format!("将第{page_number}页写入PDF失败"))?;
+        first_page_id.get_or_insert(page_id);
+    }
+
+    Ok(first_page_id)
+}
+
+/// 获取页面中唯一的图片流，页面由`PdfWriter::add_image_page`创建
+fn get_page_image_stream(doc: &Document, page_id: ObjectId) -> anyhow::Result<&Stream> {
+    let page = doc.get_dictionary(page_id)?;
+    let resources = match page.get(b"Resources")? {
+        Object::Reference(id) => doc.get_dictionary(*id)?,
+        object => object.as_dict()?,
+    };
+    let xobjects = resources.get(b"XObject")?.as_dict()?;
+    let (_, image_ref) = xobjects.iter().next().context("页面中没有图片")?;
+    let image_stream = doc.get_object(image_ref.as_reference()?)?.as_stream()?;
+    Ok(image_stream)
+}
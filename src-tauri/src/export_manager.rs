@@ -0,0 +1,625 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context};
+use indexmap::IndexMap;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::AppHandle;
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+
+use crate::export::{self, ExportArchive};
+use crate::extensions::{AnyhowErrorToStringChain, AppHandleExt};
+use crate::types::Comic;
+
+/// 最多保留多少个已结束(完成、失败或取消)的导出任务，超出的部分从最早的开始移除
+const MAX_FINISHED_JOBS: usize = 100;
+
+/// 用于管理导出任务
+///
+/// 克隆 `ExportManager` 的开销极小，性能开销几乎可以忽略不计。
+/// 可以放心地在多个线程中传递和使用它的克隆副本。
+///
+/// 具体来说：
+/// - `app`的克隆开销很小。
+/// - 其他字段都被 `Arc` 包裹，这些字段的克隆操作仅仅是增加引用计数。
+#[derive(Clone)]
+pub struct ExportManager {
+    app: AppHandle,
+    job_sem: Arc<Semaphore>,
+    thread_pool: Arc<rayon::ThreadPool>,
+    export_jobs: Arc<RwLock<IndexMap<String, ExportJob>>>,
+}
+
+/// 导出任务要导出的范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportScope {
+    /// 导出所有已下载的章节，PDF还会合并成一个
+    All,
+    /// 只导出指定ID的章节
+    Chapter(i64),
+    /// 只创建合并后的PDF，不导出单独的章节
+    Merged,
+}
+
+impl ExportScope {
+    pub fn includes_chapter(self, chapter_id: i64) -> bool {
+        match self {
+            ExportScope::All | ExportScope::Merged => true,
+            ExportScope::Chapter(id) => id == chapter_id,
+        }
+    }
+
+    pub fn creates_chapter_files(self) -> bool {
+        self != ExportScope::Merged
+    }
+
+    pub fn creates_merged_file(self) -> bool {
+        !matches!(self, ExportScope::Chapter(_))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum ExportJobState {
+    Pending,
+    Exporting,
+    Paused,
+    Cancelled,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportJobSnapshot {
+    pub id: String,
+    pub comic_id: i64,
+    pub comic_title: String,
+    pub archive: ExportArchive,
+    pub state: ExportJobState,
+    pub current: u32,
+    pub total: u32,
+}
+
+impl ExportManager {
+    pub fn new(app: AppHandle) -> anyhow::Result<Self> {
+        let (job_concurrency, thread_count) = {
+            let config = app.get_config();
+            let config = config.read();
+            (config.export_job_concurrency, config.export_thread_count)
+        };
+        // 导出是CPU密集型操作，用专门的线程池来限制导出占用的线程数，避免与下载抢占全局的rayon线程池
+        let thread_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(thread_count)
+            .thread_name(|i| format!("export-{i}"))
+            .build()
+            .context("创建导出线程池失败")?;
+
+        let manager = ExportManager {
+            app,
+            job_sem: Arc::new(Semaphore::new(job_concurrency.max(1))),
+            thread_pool: Arc::new(thread_pool),
+            export_jobs: Arc::new(RwLock::new(IndexMap::new())),
+        };
+
+        Ok(manager)
+    }
+
+    /// 创建导出任务并返回任务ID
+    ///
+    /// 如果`delete_images`为`true`，导出成功后会删除`scope`内章节的图片(保留章节元数据)
+    ///
+    /// 每部漫画只保留一个还没开始的合并任务，新的合并任务会取代之前还没开始的合并任务
+    pub fn create_export_job(
+        &self,
+        comic: Comic,
+        archive: ExportArchive,
+        scope: ExportScope,
+        delete_images: bool,
+    ) -> String {
+        let job = ExportJob::new(self, comic, archive, scope, delete_images);
+        let job_id = job.id.clone();
+        {
+            let mut jobs = self.export_jobs.write();
+            if scope == ExportScope::Merged {
+                jobs.retain(|_, old_job| {
+                    let superseded = old_job.scope == ExportScope::Merged
+                        && old_job.comic.id == job.comic.id
+                        && old_job.archive == archive
+                        && !old_job.started.load(Ordering::Relaxed)
+                        && !old_job.is_finished();
+                    if superseded {
+                        old_job.set_state(ExportJobState::Cancelled);
+                    }
+                    !superseded
+                });
+            }
+            // 先插入再开始处理，这样合并任务才能在`export_jobs`中找到比它更早的任务
+            jobs.insert(job_id.clone(), job.clone());
+        }
+        tauri::async_runtime::spawn(job.process());
+        self.prune_finished_jobs();
+        job_id
+    }
+
+    /// 已结束的导出任务超过`MAX_FINISHED_JOBS`时，移除最早的那些
+    fn prune_finished_jobs(&self) {
+        let mut jobs = self.export_jobs.write();
+        let finished_count = jobs.values().filter(|job| job.is_finished()).count();
+        let mut remove_count = finished_count.saturating_sub(MAX_FINISHED_JOBS);
+        jobs.retain(|_, job| {
+            if remove_count > 0 && job.is_finished() {
+                remove_count -= 1;
+                return false;
+            }
+            true
+        });
+    }
+
+    pub fn pause_export_job(&self, job_id: &str) -> anyhow::Result<()> {
+        use ExportJobState::{Exporting, Paused, Pending};
+        let jobs = self.export_jobs.read();
+        let Some(job) = jobs.get(job_id) else {
+            return Err(anyhow!("未找到ID为`{job_id}`的导出任务"));
+        };
+        let state = *job.state_sender.borrow();
+        if !matches!(state, Pending | Exporting) {
+            return Err(anyhow!(
+                "ID为`{job_id}`的导出任务状态为`{state:?}`，无法暂停"
+            ));
+        }
+        job.set_state(Paused);
+        Ok(())
+    }
+
+    pub fn resume_export_job(&self, job_id: &str) -> anyhow::Result<()> {
+        use ExportJobState::{Exporting, Paused, Pending};
+        let jobs = self.export_jobs.read();
+        let Some(job) = jobs.get(job_id) else {
+            return Err(anyhow!("未找到ID为`{job_id}`的导出任务"));
+        };
+        let state = *job.state_sender.borrow();
+        if state != Paused {
+            return Err(anyhow!(
+                "ID为`{job_id}`的导出任务状态为`{state:?}`，无法恢复"
+            ));
+        }
+        // 已经开始导出的任务恢复为`Exporting`，还在排队的任务恢复为`Pending`
+        if job.started.load(Ordering::Relaxed) {
+            job.set_state(Exporting);
+        } else {
+            job.set_state(Pending);
+        }
+        Ok(())
+    }
+
+    pub fn cancel_export_job(&self, job_id: &str) -> anyhow::Result<()> {
+        use ExportJobState::{Exporting, Paused, Pending};
+        let jobs = self.export_jobs.read();
+        let Some(job) = jobs.get(job_id) else {
+            return Err(anyhow!("未找到ID为`{job_id}`的导出任务"));
+        };
+        let state = *job.state_sender.borrow();
+        if !matches!(state, Pending | Exporting | Paused) {
+            return Err(anyhow!(
+                "ID为`{job_id}`的导出任务状态为`{state:?}`，无法取消"
+            ));
+        }
+        job.set_state(ExportJobState::Cancelled);
+        Ok(())
+    }
+
+    /// 暂停所有`Pending`或`Exporting`的导出任务，返回被暂停的任务的ID
+    pub fn pause_active_jobs(&self) -> Vec<String> {
+        use ExportJobState::{Exporting, Paused, Pending};
+        let jobs = self.export_jobs.read();
+        let mut paused_job_ids = Vec::new();
+        for (job_id, job) in jobs.iter() {
+            let state = *job.state_sender.borrow();
+            if matches!(state, Pending | Exporting) {
+                job.set_state(Paused);
+                paused_job_ids.push(job_id.clone());
+            }
+        }
+        paused_job_ids
+    }
+
+    /// 继续`job_ids`中仍处于`Paused`状态的导出任务
+    pub fn resume_paused_jobs(&self, job_ids: &[String]) {
+        for job_id in job_ids {
+            let is_paused = self
+                .export_jobs
+                .read()
+                .get(job_id)
+                .is_some_and(|job| *job.state_sender.borrow() == ExportJobState::Paused);
+            if is_paused {
+                let _ = self.resume_export_job(job_id);
+            }
+        }
+    }
+
+    pub fn get_export_jobs(&self) -> Vec<ExportJobSnapshot> {
+        self.export_jobs
+            .read()
+            .values()
+            .map(ExportJob::snapshot)
+            .collect()
+    }
+}
+
+/// 导出任务在导出期间持有的permit，暂停时释放，恢复后重新排队获取
+///
+/// 同一任务的多个导出线程共用这一个permit
+#[derive(Clone)]
+struct JobPermit {
+    job_sem: Arc<Semaphore>,
+    permit: Arc<Mutex<Option<OwnedSemaphorePermit>>>,
+}
+
+impl JobPermit {
+    fn new(job_sem: Arc<Semaphore>) -> Self {
+        Self {
+            job_sem,
+            permit: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn set(&self, permit: OwnedSemaphorePermit) {
+        *self.permit.lock() = Some(permit);
+    }
+
+    fn release(&self) {
+        self.permit.lock().take();
+    }
+
+    /// 阻塞当前线程，直到任务没有被暂停且持有permit，如果任务被取消则返回错误
+    fn wait_until_runnable(
+        &self,
+        state_sender: &watch::Sender<ExportJobState>,
+    ) -> anyhow::Result<()> {
+        use ExportJobState::{Cancelled, Paused};
+
+        let mut state_receiver = state_sender.subscribe();
+        loop {
+            let state = *state_receiver.borrow_and_update();
+            if state == Cancelled {
+                return Err(anyhow!("导出任务已取消"));
+            }
+
+            if state == Paused {
+                self.release();
+                tauri::async_runtime::block_on(state_receiver.wait_for(|state| *state != Paused))
+                    .context("接收导出任务状态失败")?;
+                continue;
+            }
+            // 暂停期间释放了permit，持有锁重新排队获取，这样同一任务只有一个线程在排队，
+            // 其他线程等锁释放后直接使用获取到的permit，否则它们会一直等待这个任务自己持有的permit
+            let mut permit = self.permit.lock();
+            if permit.is_some() {
+                return Ok(());
+            }
+            // 排队期间状态变化则回到循环开头重新检查
+            let job_sem = self.job_sem.clone();
+            let acquired = tauri::async_runtime::block_on(async {
+                tokio::select! {
+                    acquired = job_sem.acquire_owned() => Some(acquired),
+                    _ = state_receiver.changed() => None,
+                }
+            });
+            if let Some(acquired) = acquired {
+                *permit = Some(acquired.context("获取导出任务的permit失败")?);
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ExportJob {
+    app: AppHandle,
+    export_manager: ExportManager,
+    pub id: String,
+    pub comic: Arc<Comic>,
+    pub archive: ExportArchive,
+    pub scope: ExportScope,
+    delete_images: bool,
+    state_sender: watch::Sender<ExportJobState>,
+    started: Arc<AtomicBool>,
+    job_permit: JobPermit,
+    pub current: Arc<AtomicU32>,
+    pub total: Arc<AtomicU32>,
+}
+
+impl ExportJob {
+    fn new(
+        export_manager: &ExportManager,
+        comic: Comic,
+        archive: ExportArchive,
+        scope: ExportScope,
+        delete_images: bool,
+    ) -> Self {
+        let (state_sender, _) = watch::channel(ExportJobState::Pending);
+
+        Self {
+            app: export_manager.app.clone(),
+            export_manager: export_manager.clone(),
+            id: uuid::Uuid::new_v4().to_string(),
+            comic: Arc::new(comic),
+            archive,
+            scope,
+            delete_images,
+            state_sender,
+            started: Arc::new(AtomicBool::new(false)),
+            job_permit: JobPermit::new(export_manager.job_sem.clone()),
+            current: Arc::new(AtomicU32::new(0)),
+            total: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    async fn process(self) {
+        let comic_title = &self.comic.name;
+        let archive = self.archive;
+
+        tracing::debug!(comic_title, ?archive, "导出任务开始排队");
+        // 合并任务依赖同一漫画之前的导出任务，要等它们结束后再开始排队
+        if self.scope == ExportScope::Merged {
+            self.wait_for_earlier_jobs().await;
+        }
+
+        let Some(permit) = self.acquire_job_permit().await else {
+            return;
+        };
+        self.job_permit.set(permit);
+
+        self.started.store(true, Ordering::Relaxed);
+        self.set_state(ExportJobState::Exporting);
+        // 导出是CPU密集型操作，所以放到导出线程池中执行
+        let job = self.clone();
+        let (sender, receiver) = tokio::sync::oneshot::channel::<anyhow::Result<()>>();
+        self.export_manager.thread_pool.spawn(move || {
+            let result = match job.archive {
+                ExportArchive::Cbz => export::cbz(&job.app, &job),
+                ExportArchive::Pdf => export::pdf(&job.app, &job),
+            };
+            let _ = sender.send(result);
+        });
+        // 在tokio任务中等待导出完成，避免阻塞worker threads
+        let result = match receiver.await {
+            Ok(result) => result,
+            Err(err) => Err(anyhow::Error::from(err)),
+        };
+        self.job_permit.release();
+
+        match result {
+            // 导出的最后一步完成前任务可能被取消了，此时保持`Cancelled`，也不删除图片
+            Ok(()) if self.is_cancelled() => {
+                tracing::debug!(comic_title, ?archive, "导出任务已取消");
+            }
+            Ok(()) => {
+                tracing::info!(comic_title, ?archive, "导出成功");
+                if self.delete_images {
+                    self.delete_chapter_images();
+                }
+                self.set_state(ExportJobState::Completed);
+            }
+            Err(_) if self.is_cancelled() => {
+                tracing::debug!(comic_title, ?archive, "导出任务已取消");
+            }
+            Err(err) => {
+                let extension = archive.extension();
+                let err_title = format!("漫画`{comic_title}`导出{extension}失败");
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+                self.set_state(ExportJobState::Failed);
+            }
+        }
+    }
+
+    /// 排队获取导出permit，排队期间暂停的任务不参与排队，如果任务被取消则返回`None`
+    async fn acquire_job_permit(&self) -> Option<OwnedSemaphorePermit> {
+        let comic_title = &self.comic.name;
+        let mut state_receiver = self.state_sender.subscribe();
+
+        loop {
+            let state = match state_receiver
+                .wait_for(|state| *state != ExportJobState::Paused)
+                .await
+            {
+                Ok(state) => *state,
+                Err(err) => {
+                    let err_title = format!("`{comic_title}`的导出任务接收状态失败");
+                    let string_chain = anyhow::Error::from(err).to_string_chain();
+                    tracing::error!(err_title, message = string_chain);
+                    return None;
+                }
+            };
+
+            if state == ExportJobState::Cancelled {
+                tracing::debug!(comic_title, "导出任务在排队时被取消");
+                return None;
+            }
+
+            tokio::select! {
+                permit = self.export_manager.job_sem.clone().acquire_owned() => match permit {
+                    // 获取到permit的同时任务可能被暂停了，回到循环开头重新检查状态
+                    Ok(permit) if *self.state_sender.borrow() == ExportJobState::Pending => return Some(permit),
+                    Ok(_) => continue,
+                    Err(err) => {
+                        let err_title = format!("`{comic_title}`获取导出任务的permit失败");
+                        let string_chain = anyhow::Error::from(err).to_string_chain();
+                        tracing::error!(err_title, message = string_chain);
+
+                        self.set_state(ExportJobState::Failed);
+                        return None;
+                    }
+                },
+                _ = state_receiver.changed() => continue,
+            }
+        }
+    }
+
+    /// 任务是否已结束(完成、失败或取消)
+    fn is_finished(&self) -> bool {
+        use ExportJobState::{Cancelled, Completed, Failed};
+        matches!(*self.state_sender.borrow(), Cancelled | Completed | Failed)
+    }
+
+    /// 等待同一漫画中比当前任务更早创建的导出任务结束(完成、失败或取消)
+    async fn wait_for_earlier_jobs(&self) {
+        use ExportJobState::{Cancelled, Completed, Failed};
+        let state_receivers: Vec<watch::Receiver<ExportJobState>> = self
+            .export_manager
+            .export_jobs
+            .read()
+            .values()
+            .take_while(|job| job.id != self.id)
+            .filter(|job| job.comic.id == self.comic.id)
+            .map(|job| job.state_sender.subscribe())
+            .collect();
+
+        for mut state_receiver in state_receivers {
+            let _ = state_receiver
+                .wait_for(|state| matches!(state, Cancelled | Completed | Failed))
+                .await;
+        }
+    }
+
+    /// 删除导出范围内章节的图片，保留章节元数据
+    fn delete_chapter_images(&self) {
+        let comic_title = &self.comic.name;
+        let chapter_infos = self
+            .comic
+            .chapter_infos
+            .iter()
+            .filter(|chapter_info| chapter_info.is_downloaded.unwrap_or(false))
+            .filter(|chapter_info| !chapter_info.images_deleted)
+            .filter(|chapter_info| self.scope.includes_chapter(chapter_info.chapter_id));
+        for chapter_info in chapter_infos {
+            if let Err(err) = chapter_info.delete_images() {
+                let chapter_title = &chapter_info.chapter_title;
+                let err_title = format!("`{comic_title} - {chapter_title}`导出后删除图片失败");
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+            }
+        }
+    }
+
+    /// 在处理每个章节之前调用
+    ///
+    /// - 如果磁盘空间不足，则暂停所有任务
+    /// - 如果任务被暂停，则释放permit让排队中的任务先导出，并阻塞当前线程直到任务恢复或被取消，
+    ///   恢复后重新排队获取permit
+    /// - 如果任务被取消，则返回错误，以中止导出
+    ///
+    /// 暂停的任务仍然占用导出线程池中的一个线程，
+    /// 所以暂停的任务数达到`export_thread_count`时，新开始的任务要等暂停的任务恢复后才能执行
+    pub fn checkpoint(&self) -> anyhow::Result<()> {
+        // 磁盘空间不足时会暂停所有任务，包括当前任务
+        self.app.get_disk_space_guard().check();
+
+        self.job_permit.wait_until_runnable(&self.state_sender)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.state_sender.borrow() == ExportJobState::Cancelled
+    }
+
+    fn set_state(&self, state: ExportJobState) {
+        // 导出任务不会一直持有receiver，所以用`send_replace`，即使没有receiver也能更新状态
+        self.state_sender.send_replace(state);
+    }
+
+    fn snapshot(&self) -> ExportJobSnapshot {
+        ExportJobSnapshot {
+            id: self.id.clone(),
+            comic_id: self.comic.id,
+            comic_title: self.comic.name.clone(),
+            archive: self.archive,
+            state: *self.state_sender.borrow(),
+            current: self.current.load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+
+    use tokio::sync::{watch, Semaphore};
+
+    use super::{ExportJobState, JobPermit};
+
+    const EXPORT_THREAD_COUNT: usize = 4;
+    const CHAPTERS_PER_THREAD: usize = 3;
+
+    /// 模拟`export_job_concurrency`为1时，一个正在用多个线程并发导出多个章节的任务被暂停后恢复，
+    /// 每个线程在处理每个章节前都会调用`wait_until_runnable`
+    #[test]
+    fn paused_job_with_multiple_chapters_resumes_with_concurrency_1() {
+        let job_sem = Arc::new(Semaphore::new(1));
+        let job_permit = JobPermit::new(job_sem.clone());
+        job_permit.set(job_sem.clone().try_acquire_owned().unwrap());
+        let state_sender = Arc::new(watch::channel(ExportJobState::Exporting).0);
+
+        let (done_sender, done_receiver) = mpsc::channel();
+        {
+            let job_sem = job_sem.clone();
+            let state_sender = state_sender.clone();
+            std::thread::spawn(move || {
+                let released_while_paused = std::thread::scope(|scope| {
+                    for _ in 0..EXPORT_THREAD_COUNT {
+                        scope.spawn(|| {
+                            for _ in 0..CHAPTERS_PER_THREAD {
+                                job_permit.wait_until_runnable(&state_sender).unwrap();
+                                std::thread::sleep(Duration::from_millis(10));
+                            }
+                        });
+                    }
+                    // 暂停足够长的时间，让所有线程都在下一个章节前等待任务恢复
+                    state_sender.send_replace(ExportJobState::Paused);
+                    std::thread::sleep(Duration::from_millis(100));
+                    let released_while_paused = job_sem.available_permits() == 1;
+                    state_sender.send_replace(ExportJobState::Exporting);
+                    released_while_paused
+                });
+                // 恢复后整个任务只重新获取了一个permit
+                let reacquired = job_sem.available_permits() == 0;
+                job_permit.release();
+                let _ = done_sender.send((released_while_paused, reacquired));
+            });
+        }
+
+        let (released_while_paused, reacquired) = done_receiver
+            .recv_timeout(Duration::from_secs(10))
+            .expect("暂停后恢复的导出任务没有在10秒内完成，可能卡住了");
+        assert!(released_while_paused, "暂停期间没有释放permit");
+        assert!(reacquired, "恢复后没有重新获取permit");
+        assert_eq!(job_sem.available_permits(), 1);
+    }
+
+    #[test]
+    fn cancelled_job_stops_waiting() {
+        let job_sem = Arc::new(Semaphore::new(1));
+        let job_permit = JobPermit::new(job_sem.clone());
+        let state_sender = Arc::new(watch::channel(ExportJobState::Paused).0);
+
+        let (done_sender, done_receiver) = mpsc::channel();
+        {
+            let state_sender = state_sender.clone();
+            std::thread::spawn(move || {
+                let result = job_permit.wait_until_runnable(&state_sender);
+                let _ = done_sender.send(result.is_err());
+            });
+        }
+        std::thread::sleep(Duration::from_millis(50));
+        state_sender.send_replace(ExportJobState::Cancelled);
+
+        let cancelled = done_receiver
+            .recv_timeout(Duration::from_secs(10))
+            .expect("取消的导出任务没有停止等待");
+        assert!(cancelled, "取消后`wait_until_runnable`应返回错误");
+        assert_eq!(job_sem.available_permits(), 1);
+    }
+}
@@ -1,7 +1,15 @@
 use parking_lot::RwLock;
 use tauri::{Manager, State};
 
-use crate::{config::Config, download_manager::DownloadManager, jm_client::JmClient};
+use crate::{
+    batch_job_manager::BatchJobManager,
+    config::Config,
+    disk_space_guard::DiskSpaceGuard,
+    download_manager::{DownloadManager, TEMP_DOWNLOAD_DIR_PREFIX},
+    export_manager::ExportManager,
+    jm_client::JmClient,
+    library::LibraryUpdateReport,
+};
 
 pub trait AnyhowErrorToStringChain {
     /// 将 `anyhow::Error` 转换为chain格式
@@ -25,11 +33,10 @@ impl AnyhowErrorToStringChain for anyhow::Error {
 }
 
 pub trait PathIsImg {
-    /// 判断路径是否为图片(jpg/png/webp/gif)
+    /// 判断路径是否为图片(jpg/png/webp/gif/avif)
     fn is_img(&self) -> bool;
-
-    /// 判断路径是否为普通图片(jpg/png/webp)
-    fn is_common_img(&self) -> bool;
+    /// 判断路径是否为AVIF图片，导出PDF时无法解码AVIF
+    fn is_avif(&self) -> bool;
 }
 
 impl PathIsImg for std::path::Path {
@@ -37,20 +44,20 @@ impl PathIsImg for std::path::Path {
         self.extension()
             .and_then(|ext| ext.to_str())
             .map(str::to_lowercase)
-            .is_some_and(|ext| matches!(ext.as_str(), "jpg" | "png" | "webp" | "gif"))
+            .is_some_and(|ext| matches!(ext.as_str(), "jpg" | "png" | "webp" | "gif" | "avif"))
     }
 
-    fn is_common_img(&self) -> bool {
+    fn is_avif(&self) -> bool {
         self.extension()
             .and_then(|ext| ext.to_str())
-            .map(str::to_lowercase)
-            .is_some_and(|ext| matches!(ext.as_str(), "jpg" | "png" | "webp"))
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("avif"))
     }
 }
 
 pub trait WalkDirEntryExt {
     fn is_comic_metadata(&self) -> bool;
     fn is_chapter_metadata(&self) -> bool;
+    fn is_chapter_archive(&self) -> bool;
 }
 impl WalkDirEntryExt for walkdir::DirEntry {
     fn is_comic_metadata(&self) -> bool {
@@ -71,8 +78,24 @@ impl WalkDirEntryExt for walkdir::DirEntry {
         if self.file_name() != "章节元数据.json" {
             return false;
         }
+        // 临时下载目录中的章节元数据只用于恢复下载，此时章节还没下载完
+        let in_temp_download_dir = self
+            .path()
+            .parent()
+            .and_then(std::path::Path::file_name)
+            .is_some_and(|name| name.to_string_lossy().starts_with(TEMP_DOWNLOAD_DIR_PREFIX));
 
-        true
+        !in_temp_download_dir
+    }
+
+    fn is_chapter_archive(&self) -> bool {
+        if !self.file_type().is_file() {
+            return false;
+        }
+
+        self.path()
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("cbz"))
     }
 }
 
@@ -80,6 +103,10 @@ pub trait AppHandleExt {
     fn get_config(&self) -> State<RwLock<Config>>;
     fn get_jm_client(&self) -> State<JmClient>;
     fn get_download_manager(&self) -> State<DownloadManager>;
+    fn get_export_manager(&self) -> State<ExportManager>;
+    fn get_disk_space_guard(&self) -> State<DiskSpaceGuard>;
+    fn get_library_update_report(&self) -> State<RwLock<Option<LibraryUpdateReport>>>;
+    fn get_batch_job_manager(&self) -> State<BatchJobManager>;
 }
 
 impl AppHandleExt for tauri::AppHandle {
@@ -92,4 +119,16 @@ impl AppHandleExt for tauri::AppHandle {
     fn get_download_manager(&self) -> State<DownloadManager> {
         self.state::<DownloadManager>()
     }
+    fn get_export_manager(&self) -> State<ExportManager> {
+        self.state::<ExportManager>()
+    }
+    fn get_disk_space_guard(&self) -> State<DiskSpaceGuard> {
+        self.state::<DiskSpaceGuard>()
+    }
+    fn get_library_update_report(&self) -> State<RwLock<Option<LibraryUpdateReport>>> {
+        self.state::<RwLock<Option<LibraryUpdateReport>>>()
+    }
+    fn get_batch_job_manager(&self) -> State<BatchJobManager> {
+        self.state::<BatchJobManager>()
+    }
 }
@@ -21,11 +21,12 @@ use tauri::AppHandle;
 use crate::download_manager::IMAGE_DOMAIN;
 use crate::extensions::{AnyhowErrorToStringChain, AppHandleExt};
 use crate::responses::{
-    GetChapterRespData, GetComicRespData, GetFavoriteRespData, GetUserProfileRespData,
-    GetWeeklyInfoRespData, GetWeeklyRespData, JmResp, RedirectRespData, SearchResp, SearchRespData,
-    ToggleFavoriteRespData,
+    ComicInSearchRespData, EditFavoriteFolderRespData, GetCategoriesRespData,
+    GetCategoryFilterRespData, GetChapterRespData, GetComicRespData, GetFavoriteRespData,
+    GetUserProfileRespData, GetWeeklyInfoRespData, GetWeeklyRespData, JmResp, RedirectRespData,
+    SearchResp, SearchRespData, ToggleFavoriteRespData, ToggleType,
 };
-use crate::types::{FavoriteSort, ProxyMode, SearchSort};
+use crate::types::{ComicCategory, FavoriteSort, ProxyMode, SearchMainTag, SearchSort, SearchTime};
 use crate::utils;
 
 const APP_TOKEN_SECRET: &str = "18comicAPP";
@@ -42,8 +43,12 @@ enum ApiPath {
     GetChapter,
     GetScrambleId,
     GetFavoriteFolder,
+    EditFavoriteFolder,
     GetWeeklyInfo,
     GetWeekly,
+    GetCategories,
+    GetCategoryFilter,
+    GetLatest,
 }
 impl ApiPath {
     fn as_str(&self) -> &'static str {
@@ -57,8 +62,12 @@ impl ApiPath {
             ApiPath::GetChapter => "/chapter",
             ApiPath::GetScrambleId => "/chapter_view_template",
             ApiPath::GetFavoriteFolder => "/favorite",
+            ApiPath::EditFavoriteFolder => "/favorite_folder",
             ApiPath::GetWeeklyInfo => "/week",
             ApiPath::GetWeekly => "/week/filter",
+            ApiPath::GetCategories => "/categories",
+            ApiPath::GetCategoryFilter => "/categories/filter",
+            ApiPath::GetLatest => "/latest",
         }
     }
 }
@@ -237,17 +246,23 @@ impl JmClient {
         Ok(user_profile)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn search(
         &self,
         keyword: &str,
         page: i64,
         sort: SearchSort,
+        main_tag: SearchMainTag,
+        time: SearchTime,
+        category: ComicCategory,
     ) -> anyhow::Result<SearchResp> {
         let query = json!({
-            "main_tag": 0,
+            "main_tag": main_tag.as_i64(),
             "search_query": keyword,
             "page": page,
             "o": sort.as_str(),
+            "t": time.as_str(),
+            "c": category.as_str(),
         });
         let ts = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
         // 发送搜索请求
@@ -497,6 +512,128 @@ impl JmClient {
         Ok(get_weekly_resp_data)
     }
 
+    pub async fn get_categories(&self) -> anyhow::Result<GetCategoriesRespData> {
+        let ts = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        // 发送获取分类列表请求
+        let http_resp = self.jm_get(ApiPath::GetCategories, None, ts).await?;
+        // 检查http响应状态码
+        let status = http_resp.status();
+        let body = http_resp.text().await?;
+        if status != reqwest::StatusCode::OK {
+            return Err(anyhow!(
+                "获取分类列表失败，预料之外的状态码({status}): {body}"
+            ));
+        }
+        // 尝试将body解析为JmResp
+        let jm_resp = serde_json::from_str::<JmResp>(&body)
+            .context(format!("将body解析为JmResp失败: {body}"))?;
+        // 检查JmResp的code字段
+        if jm_resp.code != 200 {
+            return Err(anyhow!("获取分类列表失败，预料之外的code: {jm_resp:?}"));
+        }
+        // 检查JmResp的data字段
+        let data = jm_resp
+            .data
+            .as_str()
+            .context(format!("获取分类列表失败，data字段不是字符串: {jm_resp:?}"))?;
+        // 解密data字段
+        let data = decrypt_data(ts, data)?;
+        // 尝试将解密后的data字段解析为GetCategoriesRespData
+        let get_categories_resp_data = serde_json::from_str::<GetCategoriesRespData>(&data)
+            .context(format!(
+                "将解密后的data字段解析为GetCategoriesRespData失败: {data}"
+            ))?;
+        Ok(get_categories_resp_data)
+    }
+
+    /// 按分类浏览漫画，`sort`为`View`时配合`time`就是排行榜
+    pub async fn get_category_filter(
+        &self,
+        category_slug: &str,
+        page: i64,
+        sort: SearchSort,
+        time: SearchTime,
+    ) -> anyhow::Result<GetCategoryFilterRespData> {
+        let ts = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        // 时间范围是拼在排序方式后面的，例如`mv_w`表示本周点击最多
+        let o = match time {
+            SearchTime::All => sort.as_str().to_string(),
+            _ => format!("{}_{}", sort.as_str(), time.as_str()),
+        };
+        let query = json!({
+            "page": page,
+            "order": "",
+            "c": category_slug,
+            "o": o,
+        });
+        // 发送按分类浏览请求
+        let http_resp = self
+            .jm_get(ApiPath::GetCategoryFilter, Some(query), ts)
+            .await?;
+        // 检查http响应状态码
+        let status = http_resp.status();
+        let body = http_resp.text().await?;
+        if status != reqwest::StatusCode::OK {
+            return Err(anyhow!(
+                "按分类浏览失败，预料之外的状态码({status}): {body}"
+            ));
+        }
+        // 尝试将body解析为JmResp
+        let jm_resp = serde_json::from_str::<JmResp>(&body)
+            .context(format!("将body解析为JmResp失败: {body}"))?;
+        // 检查JmResp的code字段
+        if jm_resp.code != 200 {
+            return Err(anyhow!("按分类浏览失败，预料之外的code: {jm_resp:?}"));
+        }
+        // 检查JmResp的data字段
+        let data = jm_resp
+            .data
+            .as_str()
+            .context(format!("按分类浏览失败，data字段不是字符串: {jm_resp:?}"))?;
+        // 解密data字段
+        let data = decrypt_data(ts, data)?;
+        // 尝试将解密后的data字段解析为GetCategoryFilterRespData
+        let get_category_filter_resp_data =
+            serde_json::from_str::<GetCategoryFilterRespData>(&data).context(format!(
+                "将解密后的data字段解析为GetCategoryFilterRespData失败: {data}"
+            ))?;
+        Ok(get_category_filter_resp_data)
+    }
+
+    pub async fn get_latest(&self, page: i64) -> anyhow::Result<Vec<ComicInSearchRespData>> {
+        let ts = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let query = json!({"page": page});
+        // 发送获取最新更新请求
+        let http_resp = self.jm_get(ApiPath::GetLatest, Some(query), ts).await?;
+        // 检查http响应状态码
+        let status = http_resp.status();
+        let body = http_resp.text().await?;
+        if status != reqwest::StatusCode::OK {
+            return Err(anyhow!(
+                "获取最新更新失败，预料之外的状态码({status}): {body}"
+            ));
+        }
+        // 尝试将body解析为JmResp
+        let jm_resp = serde_json::from_str::<JmResp>(&body)
+            .context(format!("将body解析为JmResp失败: {body}"))?;
+        // 检查JmResp的code字段
+        if jm_resp.code != 200 {
+            return Err(anyhow!("获取最新更新失败，预料之外的code: {jm_resp:?}"));
+        }
+        // 检查JmResp的data字段
+        let data = jm_resp
+            .data
+            .as_str()
+            .context(format!("获取最新更新失败，data字段不是字符串: {jm_resp:?}"))?;
+        // 解密data字段
+        let data = decrypt_data(ts, data)?;
+        // 最新更新没有分页信息，data字段直接就是漫画列表
+        let latest = serde_json::from_str::<Vec<ComicInSearchRespData>>(&data).context(format!(
+            "将解密后的data字段解析为Vec<ComicInSearchRespData>失败: {data}"
+        ))?;
+        Ok(latest)
+    }
+
     pub async fn toggle_favorite_comic(&self, aid: i64) -> anyhow::Result<ToggleFavoriteRespData> {
         let ts = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
         let form = json!({
@@ -535,6 +672,135 @@ impl JmClient {
         Ok(toggle_favorite_resp_data)
     }
 
+    /// 收藏漫画，漫画已经在收藏夹中时什么也不做
+    ///
+    /// 收藏接口只有 收藏/取消收藏 切换，所以先检查漫画是否已收藏，返回是否真的收藏了漫画
+    pub async fn add_favorite_comic(&self, aid: i64) -> anyhow::Result<bool> {
+        let is_favorite = self.get_comic(aid).await?.is_favorite;
+        if is_favorite {
+            return Ok(false);
+        }
+        let toggle_favorite_resp_data = self.toggle_favorite_comic(aid).await?;
+        if toggle_favorite_resp_data.toggle_type != ToggleType::Add {
+            return Err(anyhow!(
+                "收藏漫画失败，预料之外的操作: {toggle_favorite_resp_data:?}"
+            ));
+        }
+        Ok(true)
+    }
+
+    /// 取消收藏漫画，漫画不在收藏夹中时什么也不做
+    ///
+    /// 收藏接口只有 收藏/取消收藏 切换，所以先检查漫画是否已收藏，返回是否真的取消收藏了漫画
+    pub async fn remove_favorite_comic(&self, aid: i64) -> anyhow::Result<bool> {
+        let is_favorite = self.get_comic(aid).await?.is_favorite;
+        if !is_favorite {
+            return Ok(false);
+        }
+        let toggle_favorite_resp_data = self.toggle_favorite_comic(aid).await?;
+        if toggle_favorite_resp_data.toggle_type != ToggleType::Remove {
+            return Err(anyhow!(
+                "取消收藏漫画失败，预料之外的操作: {toggle_favorite_resp_data:?}"
+            ));
+        }
+        Ok(true)
+    }
+
+    pub async fn create_favorite_folder(
+        &self,
+        folder_name: &str,
+    ) -> anyhow::Result<EditFavoriteFolderRespData> {
+        let form = json!({
+            "type": "add",
+            "folder_name": folder_name,
+        });
+        self.edit_favorite_folder(form, "新建收藏夹").await
+    }
+
+    pub async fn rename_favorite_folder(
+        &self,
+        folder_id: i64,
+        folder_name: &str,
+    ) -> anyhow::Result<EditFavoriteFolderRespData> {
+        let form = json!({
+            "type": "edit",
+            "folder_id": folder_id,
+            "folder_name": folder_name,
+        });
+        self.edit_favorite_folder(form, "重命名收藏夹").await
+    }
+
+    pub async fn delete_favorite_folder(
+        &self,
+        folder_id: i64,
+    ) -> anyhow::Result<EditFavoriteFolderRespData> {
+        let form = json!({
+            "type": "del",
+            "folder_id": folder_id,
+        });
+        self.edit_favorite_folder(form, "删除收藏夹").await
+    }
+
+    /// 把已收藏的漫画移动到收藏夹`folder_id`
+    pub async fn move_favorite_comic(
+        &self,
+        aid: i64,
+        folder_id: i64,
+    ) -> anyhow::Result<EditFavoriteFolderRespData> {
+        let form = json!({
+            "type": "move",
+            "folder_id": folder_id,
+            "aid": aid,
+        });
+        self.edit_favorite_folder(form, "移动收藏的漫画").await
+    }
+
+    /// 收藏夹的 新建/重命名/删除/移动漫画 都用同一个接口，由`form`中的`type`区分
+    async fn edit_favorite_folder(
+        &self,
+        form: serde_json::Value,
+        action: &str,
+    ) -> anyhow::Result<EditFavoriteFolderRespData> {
+        let ts = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        // 发送编辑收藏夹请求
+        let http_resp = self
+            .jm_post(ApiPath::EditFavoriteFolder, None, Some(form), ts)
+            .await?;
+        // 检查http响应状态码
+        let status = http_resp.status();
+        let body = http_resp.text().await?;
+        if status != reqwest::StatusCode::OK {
+            return Err(anyhow!("{action}失败，预料之外的状态码({status}): {body}"));
+        }
+        // 尝试将body解析为JmResp
+        let jm_resp = serde_json::from_str::<JmResp>(&body)
+            .context(format!("将body解析为JmResp失败: {body}"))?;
+        // 检查JmResp的code字段
+        if jm_resp.code != 200 {
+            return Err(anyhow!("{action}失败，预料之外的code: {jm_resp:?}"));
+        }
+        // 检查JmResp的data字段
+        let data = jm_resp
+            .data
+            .as_str()
+            .context(format!("{action}失败，data字段不是字符串: {jm_resp:?}"))?;
+        // 解密data字段
+        let data = decrypt_data(ts, data)?;
+        // 尝试将解密后的data字段解析为EditFavoriteFolderRespData
+        let edit_favorite_folder_resp_data =
+            serde_json::from_str::<EditFavoriteFolderRespData>(&data).context(format!(
+                "将解密后的data字段解析为EditFavoriteFolderRespData失败: {data}"
+            ))?;
+        // status不为ok时，msg是失败原因，例如收藏夹名重复
+        if edit_favorite_folder_resp_data.status != "ok" {
+            return Err(anyhow!(
+                "{action}失败: {}",
+                edit_favorite_folder_resp_data.msg
+            ));
+        }
+        Ok(edit_favorite_folder_resp_data)
+    }
+
     pub async fn get_img_data_and_format(&self, url: &str) -> anyhow::Result<(Bytes, ImageFormat)> {
         let request = self
             .img_client
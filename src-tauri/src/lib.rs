@@ -1,26 +1,37 @@
 use anyhow::Context;
 use events::{
-    DownloadAllFavoritesEvent, DownloadSleepingEvent, DownloadSpeedEvent, DownloadTaskEvent,
-    ExportCbzEvent, ExportPdfEvent, LogEvent, UpdateDownloadedComicsEvent,
+    CheckLibraryUpdatesEvent, DiskSpaceEvent, DownloadAllFavoritesEvent, DownloadComicListEvent,
+    DownloadSleepingEvent, DownloadSpeedEvent, DownloadTaskEvent, ExportCbzEvent, ExportPdfEvent,
+    LogEvent, RefreshLibraryMetadataEvent, UpdateDownloadedComicsEvent,
 };
 use parking_lot::RwLock;
 use tauri::{Manager, Wry};
 
 // TODO: 用prelude来消除警告
+use crate::batch_job_manager::BatchJobManager;
 use crate::commands::*;
 use crate::config::Config;
+use crate::disk_space_guard::DiskSpaceGuard;
 use crate::download_manager::DownloadManager;
+use crate::export_manager::ExportManager;
 use crate::jm_client::JmClient;
+use crate::library::LibraryUpdateReport;
 
+mod batch_job_manager;
 mod commands;
 mod config;
+mod disk_space_guard;
 mod download_manager;
 mod errors;
 mod events;
 mod export;
+mod export_manager;
 mod extensions;
 mod jm_client;
+mod library;
 mod logger;
+mod migrations;
+pub mod pdf_writer;
 mod responses;
 mod types;
 mod utils;
@@ -43,6 +54,9 @@ pub fn run() {
             get_favorite_folder,
             get_weekly_info,
             get_weekly,
+            get_categories,
+            get_category_filter,
+            get_latest,
             get_user_profile,
             create_download_task,
             pause_download_task,
@@ -50,27 +64,47 @@ pub fn run() {
             cancel_download_task,
             download_comic,
             download_all_favorites,
+            download_comic_list,
             update_downloaded_comics,
+            refresh_library_metadata,
+            check_library_updates,
+            get_library_update_report,
+            cancel_batch_job,
             show_path_in_file_manager,
             sync_favorite_folder,
+            create_favorite_folder,
+            rename_favorite_folder,
+            delete_favorite_folder,
+            move_favorite_comic,
+            add_favorite_comic,
+            remove_favorite_comic,
             get_downloaded_comics,
             export_cbz,
             export_pdf,
+            pause_export_job,
+            resume_export_job,
+            cancel_export_job,
+            get_export_jobs,
             get_logs_dir_size,
             get_synced_comic,
             get_synced_comic_in_favorite,
             get_synced_comic_in_search,
             get_synced_comic_in_weekly,
+            migrate_library,
         ])
         .events(tauri_specta::collect_events![
             DownloadSpeedEvent,
             DownloadSleepingEvent,
             DownloadTaskEvent,
             DownloadAllFavoritesEvent,
+            DownloadComicListEvent,
             UpdateDownloadedComicsEvent,
             ExportCbzEvent,
             ExportPdfEvent,
             LogEvent,
+            DiskSpaceEvent,
+            RefreshLibraryMetadataEvent,
+            CheckLibraryUpdatesEvent,
         ]);
 
     #[cfg(debug_assertions)]
@@ -107,9 +141,19 @@ pub fn run() {
             let jm_client = JmClient::new(app.handle().clone());
             app.manage(jm_client);
 
-            let download_manager = DownloadManager::new(app.handle().clone());
+            let download_manager = DownloadManager::new(app.handle().clone())?;
             app.manage(download_manager);
 
+            let export_manager = ExportManager::new(app.handle().clone())?;
+            app.manage(export_manager);
+
+            let disk_space_guard = DiskSpaceGuard::new(app.handle().clone());
+            app.manage(disk_space_guard);
+
+            app.manage(RwLock::new(None::<LibraryUpdateReport>));
+
+            app.manage(BatchJobManager::default());
+
             logger::init(app.handle())?;
 
             Ok(())
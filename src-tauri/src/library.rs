@@ -0,0 +1,423 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::AppHandle;
+
+use crate::extensions::{AnyhowErrorToStringChain, AppHandleExt};
+use crate::types::{ChapterInfo, Comic};
+use crate::utils;
+
+/// 漫画目录中记录服务器端改名的文件
+const RENAME_HISTORY_FILENAME: &str = "改名记录.json";
+
+/// 服务器端的一次改名
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameRecord {
+    /// 发现改名时的unix时间戳(秒)
+    pub timestamp: u64,
+    /// 为`None`表示漫画改名，否则是该章节改名
+    pub chapter_id: Option<i64>,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+/// 刷新一个已下载漫画的元数据的结果
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RefreshOutcome {
+    /// 漫画名或章节标题是否有变化
+    pub renamed: bool,
+    /// 是否移动了目录
+    pub moved: bool,
+}
+
+/// 检查库存更新的报告
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryUpdateReport {
+    /// 生成报告时的unix时间戳(秒)
+    pub checked_at: u64,
+    /// 是否被取消，被取消时报告只包含已检查过的漫画
+    pub cancelled: bool,
+    /// 检查过的漫画数量
+    pub checked_count: i64,
+    /// 有新章节或有章节在服务器上被删除的漫画
+    pub updates: Vec<ComicUpdate>,
+    pub failed: Vec<ComicUpdateFailure>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ComicUpdate {
+    /// 从服务器获取的最新数据，可以直接用来创建下载任务
+    pub comic: Comic,
+    pub local_chapter_count: i64,
+    pub remote_chapter_count: i64,
+    /// 服务器上有但本地元数据中没有的章节
+    pub new_chapters: Vec<ChapterInUpdate>,
+    /// 本地元数据中有但服务器上已经不存在的章节
+    pub removed_chapters: Vec<ChapterInUpdate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterInUpdate {
+    pub chapter_id: i64,
+    pub chapter_title: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ComicUpdateFailure {
+    pub comic_id: i64,
+    pub comic_title: String,
+    pub err_message: String,
+}
+
+impl LibraryUpdateReport {
+    pub fn new() -> Self {
+        Self {
+            checked_at: unix_timestamp(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 对比已下载漫画`local_comic`和从服务器获取的`fresh_comic`的章节，章节没有变化时返回`None`
+#[allow(clippy::cast_possible_wrap)]
+pub fn diff_comic_chapters(local_comic: &Comic, fresh_comic: Comic) -> Option<ComicUpdate> {
+    let new_chapters: Vec<ChapterInUpdate> = fresh_comic
+        .chapter_infos
+        .iter()
+        .filter(|fresh| {
+            !local_comic
+                .chapter_infos
+                .iter()
+                .any(|local| local.chapter_id == fresh.chapter_id)
+        })
+        .map(ChapterInUpdate::from)
+        .collect();
+    let removed_chapters: Vec<ChapterInUpdate> = local_comic
+        .chapter_infos
+        .iter()
+        .filter(|local| {
+            !fresh_comic
+                .chapter_infos
+                .iter()
+                .any(|fresh| fresh.chapter_id == local.chapter_id)
+        })
+        .map(ChapterInUpdate::from)
+        .collect();
+    if new_chapters.is_empty() && removed_chapters.is_empty() {
+        return None;
+    }
+
+    Some(ComicUpdate {
+        local_chapter_count: local_comic.chapter_infos.len() as i64,
+        remote_chapter_count: fresh_comic.chapter_infos.len() as i64,
+        comic: fresh_comic,
+        new_chapters,
+        removed_chapters,
+    })
+}
+
+impl From<&ChapterInfo> for ChapterInUpdate {
+    fn from(chapter_info: &ChapterInfo) -> Self {
+        Self {
+            chapter_id: chapter_info.chapter_id,
+            chapter_title: chapter_info.chapter_title.clone(),
+        }
+    }
+}
+
+/// 用从服务器获取的`fresh_comic`刷新已下载漫画`local_comic`的元数据
+///
+/// - 重写漫画元数据，以及未打包为cbz的已下载章节的章节元数据
+/// - 漫画名或章节标题有变化时，在漫画目录的`改名记录.json`中追加记录
+/// - 如果`move_dirs`为`true`，把目录移动到按当前`dir_fmt`计算出的位置
+pub fn refresh_comic_metadata(
+    app: &AppHandle,
+    local_comic: &Comic,
+    mut fresh_comic: Comic,
+    move_dirs: bool,
+) -> anyhow::Result<RefreshOutcome> {
+    let comic_title = &local_comic.name;
+    let local_comic_dir = local_comic
+        .comic_download_dir
+        .clone()
+        .context("`comic_download_dir`字段为`None`")?;
+    // 以本地的下载状态为准，同一漫画有多个下载目录时，`fresh_comic`的下载目录不一定是`local_comic`的
+    fresh_comic.comic_download_dir = Some(local_comic_dir);
+    for chapter_info in &mut fresh_comic.chapter_infos {
+        let local_chapter_info = local_comic
+            .chapter_infos
+            .iter()
+            .find(|local| local.chapter_id == chapter_info.chapter_id);
+        chapter_info.is_downloaded = local_chapter_info.and_then(|local| local.is_downloaded);
+        chapter_info.chapter_download_dir =
+            local_chapter_info.and_then(|local| local.chapter_download_dir.clone());
+    }
+    // 服务器上已经不存在的已下载章节保留在元数据中，否则它们会被当成未下载
+    let removed_chapter_infos: Vec<ChapterInfo> = local_comic
+        .chapter_infos
+        .iter()
+        .filter(|local| local.is_downloaded == Some(true))
+        .filter(|local| {
+            !fresh_comic
+                .chapter_infos
+                .iter()
+                .any(|fresh| fresh.chapter_id == local.chapter_id)
+        })
+        .cloned()
+        .collect();
+    for chapter_info in &removed_chapter_infos {
+        let chapter_title = &chapter_info.chapter_title;
+        tracing::warn!(
+            comic_title,
+            chapter_title,
+            "章节在服务器上已不存在，保留在元数据中"
+        );
+    }
+    fresh_comic.chapter_infos.extend(removed_chapter_infos);
+
+    let rename_records = find_renames(local_comic, &fresh_comic);
+
+    let mut moved = false;
+    if move_dirs {
+        if app
+            .get_download_manager()
+            .has_active_task_for_comic(fresh_comic.id)
+        {
+            tracing::warn!(comic_title, "漫画有正在进行的下载任务，跳过移动目录");
+        } else {
+            moved = move_comic_dirs(app, &mut fresh_comic).context("移动目录失败")?;
+        }
+    }
+
+    fresh_comic
+        .save_comic_metadata()
+        .context("保存漫画元数据失败")?;
+
+    for chapter_info in &fresh_comic.chapter_infos {
+        if chapter_info.is_downloaded != Some(true) {
+            continue;
+        }
+        if let Err(err) = refresh_chapter_metadata(chapter_info) {
+            let chapter_title = &chapter_info.chapter_title;
+            let err_title = format!("`{comic_title} - {chapter_title}`刷新章节元数据失败");
+            let string_chain = err.to_string_chain();
+            tracing::error!(err_title, message = string_chain);
+        }
+    }
+
+    let renamed = !rename_records.is_empty();
+    if renamed {
+        let comic_download_dir = fresh_comic
+            .comic_download_dir
+            .as_ref()
+            .context("`comic_download_dir`字段为`None`")?;
+        append_rename_records(comic_download_dir, rename_records).context("保存改名记录失败")?;
+    }
+
+    Ok(RefreshOutcome { renamed, moved })
+}
+
+/// 对比本地和服务器的漫画名及章节标题，返回有变化的部分
+fn find_renames(local_comic: &Comic, fresh_comic: &Comic) -> Vec<RenameRecord> {
+    let timestamp = unix_timestamp();
+
+    let mut rename_records = Vec::new();
+    if local_comic.name != fresh_comic.name {
+        rename_records.push(RenameRecord {
+            timestamp,
+            chapter_id: None,
+            old_name: local_comic.name.clone(),
+            new_name: fresh_comic.name.clone(),
+        });
+    }
+    for fresh_chapter_info in &fresh_comic.chapter_infos {
+        let Some(local_chapter_info) = local_comic
+            .chapter_infos
+            .iter()
+            .find(|local| local.chapter_id == fresh_chapter_info.chapter_id)
+        else {
+            continue;
+        };
+        if local_chapter_info.chapter_title != fresh_chapter_info.chapter_title {
+            rename_records.push(RenameRecord {
+                timestamp,
+                chapter_id: Some(fresh_chapter_info.chapter_id),
+                old_name: local_chapter_info.chapter_title.clone(),
+                new_name: fresh_chapter_info.chapter_title.clone(),
+            });
+        }
+    }
+    rename_records
+}
+
+/// 把`rename_records`追加到`comic_download_dir`中的`改名记录.json`
+fn append_rename_records(
+    comic_download_dir: &Path,
+    mut rename_records: Vec<RenameRecord>,
+) -> anyhow::Result<()> {
+    let history_path = comic_download_dir.join(RENAME_HISTORY_FILENAME);
+    let mut history: Vec<RenameRecord> = if history_path.exists() {
+        utils::read_metadata(&history_path)?
+    } else {
+        Vec::new()
+    };
+    history.append(&mut rename_records);
+
+    let history_json =
+        serde_json::to_string_pretty(&history).context("将改名记录序列化为json失败")?;
+    utils::write_metadata(&history_path, &history_json)?;
+
+    Ok(())
+}
+
+/// 用`chapter_info`的标题和序号更新章节元数据，保留章节元数据中其他的字段(例如`blockNumOverrides`)
+///
+/// 打包为cbz的章节的元数据在cbz中，不更新
+fn refresh_chapter_metadata(chapter_info: &ChapterInfo) -> anyhow::Result<()> {
+    if chapter_info.is_archived() {
+        return Ok(());
+    }
+    let chapter_download_dir = chapter_info
+        .chapter_download_dir
+        .as_ref()
+        .context("`chapter_download_dir`字段为`None`")?;
+    let metadata_path = chapter_download_dir.join("章节元数据.json");
+
+    let mut local_chapter_info: ChapterInfo = utils::read_metadata(&metadata_path)?;
+    if local_chapter_info.chapter_title == chapter_info.chapter_title
+        && local_chapter_info.order == chapter_info.order
+    {
+        return Ok(());
+    }
+    local_chapter_info.chapter_title = chapter_info.chapter_title.clone();
+    local_chapter_info.order = chapter_info.order;
+    local_chapter_info.chapter_download_dir = Some(chapter_download_dir.clone());
+    local_chapter_info.save_chapter_metadata()?;
+
+    Ok(())
+}
+
+/// 把漫画目录和已下载的章节目录(或cbz)移动到按当前`dir_fmt`计算出的位置，并更新`comic`中的目录字段
+///
+/// 返回是否移动了任何目录，目标位置已存在的章节会被跳过
+fn move_comic_dirs(app: &AppHandle, comic: &mut Comic) -> anyhow::Result<bool> {
+    let comic_title = comic.name.clone();
+    let mut expected_comic = comic.clone();
+    expected_comic
+        .update_download_dir_fields_by_fmt(app)
+        .context("根据fmt计算下载目录失败")?;
+
+    let old_comic_dir = comic
+        .comic_download_dir
+        .clone()
+        .context("`comic_download_dir`字段为`None`")?;
+    let new_comic_dir = expected_comic
+        .comic_download_dir
+        .clone()
+        .context("`comic_download_dir`字段为`None`")?;
+
+    let mut moved = false;
+    if old_comic_dir != new_comic_dir {
+        if new_comic_dir.exists() {
+            return Err(anyhow!(
+                "目标目录`{}`已存在，无法移动`{}`",
+                new_comic_dir.display(),
+                old_comic_dir.display()
+            ));
+        }
+        rename_path(&old_comic_dir, &new_comic_dir)?;
+        moved = true;
+        // 章节目录跟着漫画目录一起移动了
+        comic.comic_download_dir = Some(new_comic_dir.clone());
+        for chapter_info in &mut comic.chapter_infos {
+            let Some(chapter_download_dir) = &chapter_info.chapter_download_dir else {
+                continue;
+            };
+            if let Ok(relative_dir) = chapter_download_dir.strip_prefix(&old_comic_dir) {
+                chapter_info.chapter_download_dir = Some(new_comic_dir.join(relative_dir));
+            }
+        }
+        tracing::info!(
+            comic_title,
+            "将漫画目录`{}`移动到`{}`",
+            old_comic_dir.display(),
+            new_comic_dir.display()
+        );
+    }
+
+    for (chapter_info, expected_chapter_info) in comic
+        .chapter_infos
+        .iter_mut()
+        .zip(&expected_comic.chapter_infos)
+    {
+        if chapter_info.is_downloaded != Some(true) {
+            continue;
+        }
+        let chapter_title = chapter_info.chapter_title.clone();
+        let (Some(old_dir), Some(new_dir)) = (
+            chapter_info.chapter_download_dir.clone(),
+            expected_chapter_info.chapter_download_dir.clone(),
+        ) else {
+            continue;
+        };
+        if old_dir == new_dir {
+            continue;
+        }
+        // 打包为cbz的章节移动的是cbz文件
+        let (old_path, new_path) = if chapter_info.is_archived() {
+            (
+                chapter_info.get_chapter_archive_path()?,
+                expected_chapter_info.get_chapter_archive_path()?,
+            )
+        } else {
+            (old_dir, new_dir.clone())
+        };
+        if new_path.exists() {
+            tracing::warn!(
+                comic_title,
+                chapter_title,
+                "目标位置`{}`已存在，跳过移动章节",
+                new_path.display()
+            );
+            continue;
+        }
+        rename_path(&old_path, &new_path)?;
+        chapter_info.chapter_download_dir = Some(new_dir);
+        moved = true;
+        tracing::info!(
+            comic_title,
+            chapter_title,
+            "将章节`{}`移动到`{}`",
+            old_path.display(),
+            new_path.display()
+        );
+    }
+
+    Ok(moved)
+}
+
+fn rename_path(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent).context(format!("创建目录`{}`失败", parent.display()))?;
+    }
+    std::fs::rename(from, to).context(format!(
+        "将`{}`移动到`{}`失败",
+        from.display(),
+        to.display()
+    ))?;
+    Ok(())
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
@@ -0,0 +1,340 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::AppHandle;
+use walkdir::WalkDir;
+
+use crate::{
+    extensions::{AppHandleExt, WalkDirEntryExt},
+    types::{ChapterInfo, Comic},
+    utils,
+};
+
+/// 漫画元数据的迁移，第`i`个迁移将版本从`i`升级到`i + 1`
+const COMIC_MIGRATIONS: &[Migration] = &[Migration {
+    description: "为v0.15.4及之前版本的章节目录创建章节元数据",
+    migrate: comic_v0_to_v1,
+}];
+
+/// 章节元数据的迁移，第`i`个迁移将版本从`i`升级到`i + 1`
+const CHAPTER_MIGRATIONS: &[Migration] = &[Migration {
+    description: "添加`schemaVersion`字段",
+    migrate: chapter_v0_to_v1,
+}];
+
+/// 当前漫画元数据的版本，没有`schemaVersion`字段的元数据视为版本0
+#[allow(clippy::cast_possible_truncation)]
+pub const COMIC_SCHEMA_VERSION: u32 = COMIC_MIGRATIONS.len() as u32;
+/// 当前章节元数据的版本，没有`schemaVersion`字段的元数据视为版本0
+#[allow(clippy::cast_possible_truncation)]
+pub const CHAPTER_SCHEMA_VERSION: u32 = CHAPTER_MIGRATIONS.len() as u32;
+
+struct Migration {
+    description: &'static str,
+    /// 就地修改元数据的json，返回具体做了哪些修改
+    migrate: fn(&mut serde_json::Value, &MigrationContext) -> anyhow::Result<Vec<String>>,
+}
+
+struct MigrationContext<'a> {
+    /// 元数据所在的目录
+    dir: &'a Path,
+    /// 为`true`时只报告会做哪些修改，不修改任何文件
+    dry_run: bool,
+}
+
+/// 序列化元数据时，在最前面加上`schemaVersion`字段
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WithSchemaVersion<'a, T> {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub metadata: &'a T,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationReport {
+    pub metadata_path: PathBuf,
+    pub from_version: u32,
+    pub to_version: u32,
+    pub changes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationFailure {
+    pub metadata_path: PathBuf,
+    pub err_message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryMigrationReport {
+    pub dry_run: bool,
+    /// 检查过的元数据数量
+    pub checked_count: u32,
+    /// 需要迁移(`dry_run`为`false`时是已迁移)的元数据
+    pub migrated: Vec<MigrationReport>,
+    pub failed: Vec<MigrationFailure>,
+}
+
+/// 如果漫画元数据`metadata_path`的版本低于`COMIC_SCHEMA_VERSION`，则就地迁移到最新版本
+///
+/// 无需迁移时返回`None`，`dry_run`为`true`时只返回报告，不修改任何文件
+pub fn migrate_comic_metadata(
+    metadata_path: &Path,
+    dry_run: bool,
+) -> anyhow::Result<Option<MigrationReport>> {
+    let comic_json = utils::read_metadata(metadata_path)?;
+    let (_, report) = migrate_comic_json(metadata_path, comic_json, dry_run)?;
+    Ok(report)
+}
+
+/// 读取漫画元数据`metadata_path`，版本低于`COMIC_SCHEMA_VERSION`时先就地迁移，返回迁移后的json
+///
+/// 与先调用`migrate_comic_metadata`再读取相比，元数据只需要读取和解析一次
+pub fn read_comic_metadata(metadata_path: &Path) -> anyhow::Result<serde_json::Value> {
+    let comic_json = utils::read_metadata(metadata_path)?;
+    let (comic_json, _) = migrate_comic_json(metadata_path, comic_json, false)?;
+    Ok(comic_json)
+}
+
+/// 对已读取的漫画元数据`comic_json`执行尚未执行的迁移，返回迁移后的json和报告，无需迁移时报告为`None`
+fn migrate_comic_json(
+    metadata_path: &Path,
+    mut comic_json: serde_json::Value,
+    dry_run: bool,
+) -> anyhow::Result<(serde_json::Value, Option<MigrationReport>)> {
+    let Some(report) = run_migrations(metadata_path, &mut comic_json, COMIC_MIGRATIONS, dry_run)?
+    else {
+        return Ok((comic_json, None));
+    };
+    if dry_run {
+        log_report(&report, dry_run);
+        return Ok((comic_json, Some(report)));
+    }
+
+    let mut comic = Comic::deserialize(&comic_json).context(format!(
+        "将迁移后的`{}`反序列化为Comic失败",
+        metadata_path.display()
+    ))?;
+    comic.comic_download_dir = Some(parent_dir(metadata_path)?.to_path_buf());
+    comic
+        .save_comic_metadata()
+        .context(format!("保存迁移后的`{}`失败", metadata_path.display()))?;
+
+    log_report(&report, dry_run);
+    Ok((comic_json, Some(report)))
+}
+
+/// 如果章节元数据`metadata_path`的版本低于`CHAPTER_SCHEMA_VERSION`，则就地迁移到最新版本
+///
+/// 无需迁移时返回`None`，`dry_run`为`true`时只返回报告，不修改任何文件
+pub fn migrate_chapter_metadata(
+    metadata_path: &Path,
+    dry_run: bool,
+) -> anyhow::Result<Option<MigrationReport>> {
+    let Some((chapter_json, report)) =
+        migrate_metadata(metadata_path, CHAPTER_MIGRATIONS, dry_run)?
+    else {
+        return Ok(None);
+    };
+    if dry_run {
+        log_report(&report, dry_run);
+        return Ok(Some(report));
+    }
+
+    let mut chapter_info: ChapterInfo = serde_json::from_value(chapter_json).context(format!(
+        "将迁移后的`{}`反序列化为ChapterInfo失败",
+        metadata_path.display()
+    ))?;
+    chapter_info.chapter_download_dir = Some(parent_dir(metadata_path)?.to_path_buf());
+    chapter_info
+        .save_chapter_metadata()
+        .context(format!("保存迁移后的`{}`失败", metadata_path.display()))?;
+
+    log_report(&report, dry_run);
+    Ok(Some(report))
+}
+
+/// 检查下载目录中所有漫画和章节的元数据，把旧版本的迁移到最新版本
+///
+/// 单个元数据迁移失败不会中断整个过程，失败的元数据记录在报告的`failed`中
+pub fn migrate_library(app: &AppHandle, dry_run: bool) -> LibraryMigrationReport {
+    let download_dir = app.get_config().read().download_dir.clone();
+    let mut report = LibraryMigrationReport {
+        dry_run,
+        ..Default::default()
+    };
+    if !download_dir.exists() {
+        return report;
+    }
+
+    // 先迁移漫画元数据，漫画元数据的迁移可能会创建章节元数据
+    let mut comic_metadata_paths = Vec::new();
+    let mut chapter_metadata_paths = Vec::new();
+    for entry in WalkDir::new(&download_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+    {
+        if entry.is_comic_metadata() {
+            comic_metadata_paths.push(entry.into_path());
+        } else if entry.is_chapter_metadata() {
+            chapter_metadata_paths.push(entry.into_path());
+        }
+    }
+
+    let comic_results = comic_metadata_paths
+        .into_iter()
+        .map(|path| (migrate_comic_metadata(&path, dry_run), path));
+    let chapter_results = chapter_metadata_paths
+        .into_iter()
+        .map(|path| (migrate_chapter_metadata(&path, dry_run), path));
+    for (result, metadata_path) in comic_results.chain(chapter_results) {
+        report.checked_count += 1;
+        match result {
+            Ok(Some(migration_report)) => report.migrated.push(migration_report),
+            Ok(None) => {}
+            Err(err) => report.failed.push(MigrationFailure {
+                metadata_path,
+                err_message: format!("{err:#}"),
+            }),
+        }
+    }
+
+    report
+}
+
+/// 读取元数据并依次执行`migrations`中尚未执行的迁移，返回迁移后的json和报告，无需迁移时返回`None`
+fn migrate_metadata(
+    metadata_path: &Path,
+    migrations: &[Migration],
+    dry_run: bool,
+) -> anyhow::Result<Option<(serde_json::Value, MigrationReport)>> {
+    let mut json: serde_json::Value = utils::read_metadata(metadata_path)?;
+    let report = run_migrations(metadata_path, &mut json, migrations, dry_run)?;
+    Ok(report.map(|report| (json, report)))
+}
+
+/// 对从`metadata_path`读取的`json`就地执行`migrations`中尚未执行的迁移，返回报告，无需迁移时返回`None`
+fn run_migrations(
+    metadata_path: &Path,
+    json: &mut serde_json::Value,
+    migrations: &[Migration],
+    dry_run: bool,
+) -> anyhow::Result<Option<MigrationReport>> {
+    #[allow(clippy::cast_possible_truncation)]
+    let latest_version = migrations.len() as u32;
+    let from_version = match json.get("schemaVersion") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .context(format!(
+                "`{}`的`schemaVersion`字段不是合法的版本号",
+                metadata_path.display()
+            ))?,
+    };
+    if from_version == latest_version {
+        return Ok(None);
+    }
+    if from_version > latest_version {
+        return Err(anyhow!(
+            "`{}`的版本为{from_version}，高于当前支持的版本{latest_version}，可能是由更新版本的程序创建的",
+            metadata_path.display()
+        ));
+    }
+
+    let ctx = MigrationContext {
+        dir: parent_dir(metadata_path)?,
+        dry_run,
+    };
+    let mut changes = Vec::new();
+    for (version, migration) in migrations.iter().enumerate().skip(from_version as usize) {
+        let mut migration_changes = (migration.migrate)(json, &ctx).context(format!(
+            "`{}`从版本{version}迁移到版本{}失败",
+            metadata_path.display(),
+            version + 1
+        ))?;
+        changes.push(format!(
+            "{version} -> {}: {}",
+            version + 1,
+            migration.description
+        ));
+        changes.append(&mut migration_changes);
+    }
+
+    let report = MigrationReport {
+        metadata_path: metadata_path.to_path_buf(),
+        from_version,
+        to_version: latest_version,
+        changes,
+    };
+    Ok(Some(report))
+}
+
+fn log_report(report: &MigrationReport, dry_run: bool) {
+    let MigrationReport {
+        metadata_path,
+        from_version,
+        to_version,
+        changes,
+    } = report;
+    let action = if dry_run { "需要" } else { "已" };
+    tracing::info!(
+        "`{}`{action}从版本{from_version}迁移到版本{to_version}:\n{}",
+        metadata_path.display(),
+        changes.join("\n")
+    );
+}
+
+fn parent_dir(metadata_path: &Path) -> anyhow::Result<&Path> {
+    metadata_path
+        .parent()
+        .context(format!("`{}`没有父目录", metadata_path.display()))
+}
+
+/// v0.15.4及之前的版本没有章节元数据，章节目录名就是章节标题，为这些章节目录创建章节元数据
+fn comic_v0_to_v1(
+    comic_json: &mut serde_json::Value,
+    ctx: &MigrationContext,
+) -> anyhow::Result<Vec<String>> {
+    let chapter_infos = comic_json
+        .get("chapterInfos")
+        .and_then(serde_json::Value::as_array)
+        .context("没有`chapterInfos`字段")?;
+
+    let mut changes = Vec::new();
+    for chapter_json in chapter_infos {
+        let mut chapter_info: ChapterInfo = serde_json::from_value(chapter_json.clone())
+            .context("将`chapterInfos`中的元素反序列化为ChapterInfo失败")?;
+        let old_chapter_dir = ctx.dir.join(&chapter_info.chapter_title);
+        let old_chapter_metadata_exists = old_chapter_dir.join("章节元数据.json").exists();
+        if !old_chapter_dir.is_dir() || old_chapter_metadata_exists {
+            continue;
+        }
+
+        changes.push(format!(
+            "为旧版本的章节目录`{}`创建章节元数据",
+            old_chapter_dir.display()
+        ));
+        if ctx.dry_run {
+            continue;
+        }
+        chapter_info.chapter_download_dir = Some(old_chapter_dir);
+        chapter_info.is_downloaded = Some(true);
+        chapter_info.save_chapter_metadata()?;
+    }
+
+    Ok(changes)
+}
+
+/// 章节元数据的结构没有变化，只需要加上版本号
+fn chapter_v0_to_v1(
+    _chapter_json: &mut serde_json::Value,
+    _ctx: &MigrationContext,
+) -> anyhow::Result<Vec<String>> {
+    Ok(Vec::new())
+}
@@ -0,0 +1,428 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context};
+use lopdf::{
+    content::{Content, Operation},
+    dictionary, Dictionary, Object, ObjectId, Stream, StringFormat,
+};
+
+/// 边生成边写入的PDF写入器
+///
+/// 每个对象生成后立即写入文件，内存中只保留对象的偏移量、页面ID和书签等少量信息，
+/// 所以无论PDF有多少页，峰值内存都只与单张图片的大小有关
+pub struct PdfWriter {
+    writer: CountingWriter<BufWriter<File>>,
+    /// 下标为`对象ID - 1`，值为对象在文件中的偏移量
+    offsets: Vec<u64>,
+    pages_id: ObjectId,
+    page_ids: Vec<ObjectId>,
+    /// 书签的标题和对应的页面ID
+    bookmarks: Vec<(String, ObjectId)>,
+    /// 页码标签的起始页下标和对应的标签字典
+    page_labels: Vec<(usize, Dictionary)>,
+}
+
+impl PdfWriter {
+    pub fn create(save_path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::create(save_path).context(format!("创建文件`{}`失败", save_path.display()))?;
+        let mut writer = CountingWriter {
+            inner: BufWriter::new(file),
+            count: 0,
+        };
+        // 第二行的注释包含非ASCII字符，用于告诉其他程序这是一个二进制文件
+        writer.write_all(b"%PDF-1.5\n%\xE2\xE3\xCF\xD3\n")?;
+
+        let mut pdf_writer = PdfWriter {
+            writer,
+            offsets: vec![],
+            pages_id: (0, 0),
+            page_ids: vec![],
+            bookmarks: vec![],
+            page_labels: vec![],
+        };
+        // 先预留"Pages"对象的 ID，每个页面都需要引用它，等所有页面写完后再写入"Pages"对象
+        pdf_writer.pages_id = pdf_writer.new_object_id();
+
+        Ok(pdf_writer)
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.page_ids.len()
+    }
+
+    /// 创建一个显示`image_stream`的页面，返回页面的 ID
+    ///
+    /// - `(width, height)`为图片的像素尺寸
+    /// - 如果`page_size`不为`None`，图片会保持宽高比缩放到页面内并居中，否则页面尺寸与图片的像素尺寸一致
+    #[allow(clippy::similar_names)]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn add_image_page(
+        &mut self,
+        image_stream: Stream,
+        (width, height): (u32, u32),
+        page_size: Option<(f32, f32)>,
+    ) -> anyhow::Result<ObjectId> {
+        // 宽或高为0时无法计算缩放比例，会在PDF中写入NaN或inf
+        if width == 0 || height == 0 {
+            return Err(anyhow!("图片尺寸`{width}x{height}`无效"));
+        }
+        // 将图片流写入文件
+        let img_id = self.add_object(image_stream)?;
+        // 图片的名称，用于 Do 操作在页面上显示图片
+        let img_name = format!("X{}", img_id.0);
+
+        let (width, height) = (width as f32, height as f32);
+        // 计算页面尺寸，以及图片在页面上的位置和大小
+        let (page_width, page_height, x, y, draw_width, draw_height) = match page_size {
+            Some((page_width, page_height)) => {
+                let scale = (page_width / width).min(page_height / height);
+                let (draw_width, draw_height) = (width * scale, height * scale);
+                let x = (page_width - draw_width) / 2.0;
+                let y = (page_height - draw_height) / 2.0;
+                (page_width, page_height, x, y, draw_width, draw_height)
+            }
+            None => (width, height, 0.0, 0.0, width, height),
+        };
+        // 用于设置图片在页面上的位置和大小
+        let cm_operation = Operation::new(
+            "cm",
+            vec![
+                draw_width.into(),
+                0.into(),
+                0.into(),
+                draw_height.into(),
+                x.into(),
+                y.into(),
+            ],
+        );
+        // 用于显示图片
+        let do_operation = Operation::new("Do", vec![Object::Name(img_name.as_bytes().to_vec())]);
+        // 因为每个页面只有一张图片，所以没必要用 q 和 Q 操作保存和恢复图形状态
+        let content = Content {
+            operations: vec![cm_operation, do_operation],
+        };
+        let mut content_stream = Stream::new(dictionary! {}, content.encode()?);
+        // 压缩失败就用未压缩的内容
+        let _ = content_stream.compress();
+        let content_id = self.add_object(content_stream)?;
+        // Do 操作只能引用 XObject(所以前面定义的 Do 操作的参数是 img_name, 而不是 img_id)
+        let page_id = self.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => self.pages_id,
+            "Contents" => content_id,
+            "MediaBox" => vec![0.into(), 0.into(), page_width.into(), page_height.into()],
+            "Resources" => dictionary! {
+                "XObject" => dictionary! { img_name => img_id },
+            },
+        })?;
+        // 记录新创建的页面的 ID
+        self.page_ids.push(page_id);
+
+        Ok(page_id)
+    }
+
+    /// 添加一个指向`page_id`的书签
+    pub fn add_bookmark(&mut self, title: String, page_id: ObjectId) {
+        self.bookmarks.push((title, page_id));
+    }
+
+    /// 从下标为`page_index`的页面开始使用`label`作为页码标签
+    pub fn add_page_label(&mut self, page_index: usize, label: Dictionary) {
+        self.page_labels.push((page_index, label));
+    }
+
+    /// 写入"Pages"、书签、"Catalog"、文档信息字典和交叉引用表，完成PDF
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn finish(mut self, info: Dictionary) -> anyhow::Result<()> {
+        // 写入预留的"Pages"对象
+        let pages_dict = dictionary! {
+            "Type" => "Pages",
+            "Count" => self.page_ids.len() as u32,
+            "Kids" => self.page_ids.iter().copied().map(Object::Reference).collect::<Vec<_>>(),
+        };
+        self.write_object(self.pages_id, &Object::Dictionary(pages_dict))?;
+
+        let mut catalog_dict = dictionary! {
+            "Type" => "Catalog",
+            "Pages" => self.pages_id,
+        };
+        // 写入书签
+        if !self.bookmarks.is_empty() {
+            let outlines_id = self.new_object_id();
+            let bookmarks = std::mem::take(&mut self.bookmarks);
+            let item_ids: Vec<ObjectId> = bookmarks.iter().map(|_| self.new_object_id()).collect();
+            for (i, (title, page_id)) in bookmarks.into_iter().enumerate() {
+                let mut item_dict = dictionary! {
+                    "Title" => lopdf::text_string(&title),
+                    "Parent" => outlines_id,
+                    "Dest" => vec![page_id.into(), "Fit".into()],
+                };
+                if i > 0 {
+                    item_dict.set("Prev", item_ids[i - 1]);
+                }
+                if let Some(next_id) = item_ids.get(i + 1) {
+                    item_dict.set("Next", *next_id);
+                }
+                self.write_object(item_ids[i], &Object::Dictionary(item_dict))?;
+            }
+            let outlines_dict = dictionary! {
+                "Type" => "Outlines",
+                "First" => item_ids[0],
+                "Last" => item_ids[item_ids.len() - 1],
+                "Count" => item_ids.len() as i64,
+            };
+            self.write_object(outlines_id, &Object::Dictionary(outlines_dict))?;
+            catalog_dict.set("Outlines", outlines_id);
+        }
+        // 写入页码标签
+        if !self.page_labels.is_empty() {
+            let nums: Vec<Object> = std::mem::take(&mut self.page_labels)
+                .into_iter()
+                .flat_map(|(page_index, label)| {
+                    [
+                        Object::Integer(page_index as i64),
+                        Object::Dictionary(label),
+                    ]
+                })
+                .collect();
+            catalog_dict.set("PageLabels", dictionary! { "Nums" => nums });
+        }
+        let catalog_id = self.add_object(catalog_dict)?;
+        let info_id = self.add_object(info)?;
+        // 写入交叉引用表
+        let xref_offset = self.writer.count;
+        let size = self.offsets.len() + 1;
+        writeln!(self.writer, "xref\n0 {size}\n0000000000 65535 f ")?;
+        for offset in &self.offsets {
+            writeln!(self.writer, "{offset:010} 00000 n ")?;
+        }
+        // 写入trailer
+        let trailer = dictionary! {
+            "Size" => size as i64,
+            "Root" => catalog_id,
+            "Info" => info_id,
+        };
+        self.writer.write_all(b"trailer\n")?;
+        write_object(&mut self.writer, &Object::Dictionary(trailer))?;
+        writeln!(self.writer, "\nstartxref\n{xref_offset}\n%EOF")?;
+
+        self.writer.flush().context("将PDF写入文件失败")?;
+
+        Ok(())
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn new_object_id(&mut self) -> ObjectId {
+        // 偏移量在写入对象时再填
+        self.offsets.push(0);
+        (self.offsets.len() as u32, 0)
+    }
+
+    fn add_object(&mut self, object: impl Into<Object>) -> anyhow::Result<ObjectId> {
+        let id = self.new_object_id();
+        self.write_object(id, &object.into())?;
+        Ok(id)
+    }
+
+    fn write_object(&mut self, id: ObjectId, object: &Object) -> anyhow::Result<()> {
+        self.offsets[id.0 as usize - 1] = self.writer.count;
+        writeln!(self.writer, "{} {} obj", id.0, id.1)?;
+        write_object(&mut self.writer, object)?;
+        self.writer.write_all(b"\nendobj\n")?;
+        Ok(())
+    }
+}
+
+/// 记录已写入字节数的writer，用于计算对象在文件中的偏移量
+struct CountingWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.count += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// 将`object`序列化为PDF语法写入`writer`
+fn write_object(writer: &mut impl Write, object: &Object) -> std::io::Result<()> {
+    match object {
+        Object::Null => writer.write_all(b"null"),
+        Object::Boolean(value) => write!(writer, "{value}"),
+        Object::Integer(value) => write!(writer, "{value}"),
+        Object::Real(value) => write!(writer, "{value}"),
+        Object::Name(name) => write_name(writer, name),
+        Object::String(text, StringFormat::Literal) => {
+            writer.write_all(b"(")?;
+            for &byte in text {
+                // 转义所有括号、反斜杠和回车，这样就不用关心括号是否配对
+                match byte {
+                    b'(' | b')' | b'\\' => writer.write_all(&[b'\\', byte])?,
+                    b'\r' => writer.write_all(b"\\r")?,
+                    _ => writer.write_all(&[byte])?,
+                }
+            }
+            writer.write_all(b")")
+        }
+        Object::String(text, StringFormat::Hexadecimal) => {
+            writer.write_all(b"<")?;
+            for byte in text {
+                write!(writer, "{byte:02X}")?;
+            }
+            writer.write_all(b">")
+        }
+        Object::Array(array) => {
+            writer.write_all(b"[")?;
+            for (i, item) in array.iter().enumerate() {
+                if i > 0 {
+                    writer.write_all(b" ")?;
+                }
+                write_object(writer, item)?;
+            }
+            writer.write_all(b"]")
+        }
+        Object::Dictionary(dict) => write_dictionary(writer, dict),
+        Object::Stream(stream) => {
+            write_dictionary(writer, &stream.dict)?;
+            writer.write_all(b"\nstream\n")?;
+            writer.write_all(&stream.content)?;
+            writer.write_all(b"\nendstream")
+        }
+        Object::Reference(id) => write!(writer, "{} {} R", id.0, id.1),
+    }
+}
+
+fn write_dictionary(writer: &mut impl Write, dict: &Dictionary) -> std::io::Result<()> {
+    writer.write_all(b"<<")?;
+    for (key, value) in dict {
+        write_name(writer, key)?;
+        writer.write_all(b" ")?;
+        write_object(writer, value)?;
+    }
+    writer.write_all(b">>")
+}
+
+fn write_name(writer: &mut impl Write, name: &[u8]) -> std::io::Result<()> {
+    writer.write_all(b"/")?;
+    for &byte in name {
+        // 空白字符、分隔符以及可打印ASCII范围外的字节需要编码为#xx
+        if b" \t\n\r\x0C()<>[]{}/%#".contains(&byte) || !(33..=126).contains(&byte) {
+            write!(writer, "#{byte:02X}")?;
+        } else {
+            writer.write_all(&[byte])?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use lopdf::{dictionary, Document, Object, Stream};
+
+    use super::PdfWriter;
+
+    const PAGES_PER_CHAPTER: usize = 20;
+    const IMG_WIDTH: u32 = 256;
+    const IMG_HEIGHT: u32 = 384;
+
+    fn temp_pdf_path() -> PathBuf {
+        std::env::temp_dir().join(format!("pdf-writer-test-{}.pdf", uuid::Uuid::new_v4()))
+    }
+
+    /// 生成一张未压缩的灰度图片流，每张图片的内容都不同
+    #[allow(clippy::cast_possible_truncation)]
+    fn synthetic_image_stream(seed: usize) -> Stream {
+        let content = (0..IMG_WIDTH * IMG_HEIGHT)
+            .map(|i| (i as usize).wrapping_mul(31).wrapping_add(seed) as u8)
+            .collect::<Vec<u8>>();
+        let dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => IMG_WIDTH,
+            "Height" => IMG_HEIGHT,
+            "ColorSpace" => "DeviceGray",
+            "BitsPerComponent" => 8,
+        };
+        Stream::new(dict, content)
+    }
+
+    /// 边生成图片边写入`chapter_count`个章节
+    fn write_synthetic_pdf(save_path: &std::path::Path, chapter_count: usize) {
+        let mut writer = PdfWriter::create(save_path).unwrap();
+        for chapter in 0..chapter_count {
+            let first_page_index = writer.page_count();
+            let mut first_page_id = None;
+            for page in 0..PAGES_PER_CHAPTER {
+                let image_stream = synthetic_image_stream(chapter * PAGES_PER_CHAPTER + page);
+                let page_size = Some((595.0, 842.0));
+                let page_id = writer
+                    .add_image_page(image_stream, (IMG_WIDTH, IMG_HEIGHT), page_size)
+                    .unwrap();
+                first_page_id.get_or_insert(page_id);
+            }
+            writer.add_bookmark(format!("第{}话", chapter + 1), first_page_id.unwrap());
+            writer.add_page_label(first_page_index, dictionary! { "S" => "D" });
+        }
+        writer.finish(dictionary! {}).unwrap();
+    }
+
+    #[test]
+    fn streamed_pdf_has_every_page_and_image() {
+        let chapter_count = 10;
+        let save_path = temp_pdf_path();
+        write_synthetic_pdf(&save_path, chapter_count);
+
+        let doc = Document::load(&save_path).unwrap();
+        let pages = doc.get_pages();
+        assert_eq!(pages.len(), chapter_count * PAGES_PER_CHAPTER);
+        for page_id in pages.into_values() {
+            let page = doc.get_dictionary(page_id).unwrap();
+            let resources = page.get(b"Resources").unwrap().as_dict().unwrap();
+            let xobjects = resources.get(b"XObject").unwrap().as_dict().unwrap();
+            assert_eq!(xobjects.len(), 1);
+            let (_, image_ref) = xobjects.iter().next().unwrap();
+            let image_id = image_ref.as_reference().unwrap();
+            let image_stream = doc.get_object(image_id).unwrap().as_stream().unwrap();
+            let get_i64 = |key: &[u8]| image_stream.dict.get(key).and_then(Object::as_i64);
+            assert_eq!(get_i64(b"Width").unwrap(), i64::from(IMG_WIDTH));
+            assert_eq!(get_i64(b"Height").unwrap(), i64::from(IMG_HEIGHT));
+            assert_eq!(
+                image_stream.content.len(),
+                (IMG_WIDTH * IMG_HEIGHT) as usize
+            );
+        }
+
+        let _ = std::fs::remove_file(save_path);
+    }
+
+    #[test]
+    fn zero_sized_image_is_rejected() {
+        let save_path = temp_pdf_path();
+        let mut writer = PdfWriter::create(&save_path).unwrap();
+        let page_size = Some((595.0, 842.0));
+        for size in [(0, IMG_HEIGHT), (IMG_WIDTH, 0)] {
+            let image_stream = synthetic_image_stream(0);
+            let result = writer.add_image_page(image_stream, size, page_size);
+            assert!(result.is_err(), "尺寸为{size:?}的图片应该被拒绝");
+        }
+        assert_eq!(writer.page_count(), 0);
+
+        drop(writer);
+        let _ = std::fs::remove_file(save_path);
+    }
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::{string_to_i64, ComicInSearchRespData};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GetCategoriesRespData {
+    pub categories: Vec<CategoryInCategoriesRespData>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryInCategoriesRespData {
+    #[serde(deserialize_with = "string_to_i64")]
+    pub id: i64,
+    pub name: String,
+    /// 用于`/categories/filter`的`c`参数
+    pub slug: String,
+    #[serde(rename = "total_albums", default, deserialize_with = "string_to_i64")]
+    pub total_albums: i64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GetCategoryFilterRespData {
+    #[serde(deserialize_with = "string_to_i64")]
+    pub total: i64,
+    pub content: Vec<ComicInSearchRespData>,
+}
@@ -1,3 +1,5 @@
+mod edit_favorite_folder_resp_data;
+mod get_categories_resp_data;
 mod get_chapter_resp_data;
 mod get_comic_resp_data;
 mod get_favorite_resp_data;
@@ -7,6 +9,8 @@ mod get_weekly_resp_data;
 mod search_resp;
 mod toggle_favorite_resp_data;
 
+pub use edit_favorite_folder_resp_data::*;
+pub use get_categories_resp_data::*;
 pub use get_chapter_resp_data::*;
 pub use get_comic_resp_data::*;
 pub use get_favorite_resp_data::*;
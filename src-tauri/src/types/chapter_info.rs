@@ -1,8 +1,22 @@
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use serde::{Deserialize, Serialize};
 use specta::Type;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    extensions::PathIsImg,
+    migrations::{self, WithSchemaVersion},
+    utils,
+};
+
+use super::{Comic, ComicInfo};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
@@ -14,6 +28,15 @@ pub struct ChapterInfo {
     pub is_downloaded: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub chapter_download_dir: Option<PathBuf>,
+    /// 下载时自动检测出的与计算结果不一致的分块数，key为图片的序号
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub block_num_overrides: BTreeMap<String, u32>,
+    /// 原图是GIF的图片保存的扩展名(gif或webp)，key为图片的序号
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub gif_page_exts: BTreeMap<String, String>,
+    /// 导出后图片已被删除，只剩章节元数据，这样的章节不能再导出
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub images_deleted: bool,
 }
 
 impl ChapterInfo {
@@ -51,12 +74,175 @@ impl ChapterInfo {
         std::fs::create_dir_all(chapter_download_dir)
             .context(format!("创建目录`{}`失败", chapter_download_dir.display()))?;
 
-        let chapter_json =
-            serde_json::to_string_pretty(&chapter_info).context("将ChapterInfo序列化为json失败")?;
+        let chapter_json = serde_json::to_string_pretty(&WithSchemaVersion {
+            schema_version: migrations::CHAPTER_SCHEMA_VERSION,
+            metadata: &chapter_info,
+        })
+        .context("将ChapterInfo序列化为json失败")?;
 
-        std::fs::write(&metadata_path, chapter_json)
+        utils::write_metadata(&metadata_path, &chapter_json)
             .context(format!("写入文件`{}`失败", metadata_path.display()))?;
 
         Ok(())
     }
+
+    /// 删除章节下载目录中的图片，保留章节元数据，这样章节仍被视为已下载
+    ///
+    /// 删除后在章节元数据中标记`images_deleted`，以免之后用空章节覆盖已导出的文件
+    pub fn delete_images(&self) -> anyhow::Result<()> {
+        let chapter_download_dir = self
+            .chapter_download_dir
+            .as_ref()
+            .context("`chapter_download_dir`字段为`None`")?;
+
+        // 以cbz形式保存的章节没有章节目录，图片就是章节本身，不删除
+        if !chapter_download_dir.exists() {
+            return Ok(());
+        }
+
+        let entries = std::fs::read_dir(chapter_download_dir)
+            .context(format!("读取目录`{}`失败", chapter_download_dir.display()))?;
+        for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+            if !path.is_img() {
+                continue;
+            }
+            std::fs::remove_file(&path).context(format!("删除`{}`失败", path.display()))?;
+        }
+
+        let mut chapter_info = self.clone();
+        chapter_info.images_deleted = true;
+        chapter_info
+            .save_chapter_metadata()
+            .context("在章节元数据中标记图片已删除失败")?;
+
+        Ok(())
+    }
+
+    /// 章节打包后的cbz路径，即`chapter_download_dir`加上`.cbz`后缀
+    pub fn get_chapter_archive_path(&self) -> anyhow::Result<PathBuf> {
+        let chapter_download_dir = self
+            .chapter_download_dir
+            .as_ref()
+            .context("`chapter_download_dir`字段为`None`")?;
+
+        let mut archive_path = OsString::from(chapter_download_dir.as_os_str());
+        archive_path.push(".cbz");
+
+        Ok(PathBuf::from(archive_path))
+    }
+
+    /// 章节是否以cbz的形式保存
+    pub fn is_archived(&self) -> bool {
+        self.get_chapter_archive_path()
+            .is_ok_and(|archive_path| archive_path.is_file())
+    }
+
+    /// 将章节目录打包为cbz，打包成功后删除章节目录
+    ///
+    /// cbz中包含所有图片、`ComicInfo.xml`和`章节元数据.json`
+    pub fn pack_into_archive(&self, comic: &Comic) -> anyhow::Result<()> {
+        let chapter_download_dir = self
+            .chapter_download_dir
+            .as_ref()
+            .context("`chapter_download_dir`字段为`None`")?;
+        let archive_path = self.get_chapter_archive_path()?;
+        // 先写到临时文件，写完再重命名，避免中途失败留下不完整的cbz
+        let mut temp_archive_path = OsString::from(archive_path.as_os_str());
+        temp_archive_path.push(".tmp");
+        let temp_archive_path = PathBuf::from(temp_archive_path);
+
+        let cfg = yaserde::ser::Config {
+            perform_indent: true,
+            ..Default::default()
+        };
+        let comic_info = ComicInfo::from(comic, self);
+        let comic_info_xml = yaserde::ser::to_string_with_config(&comic_info, &cfg)
+            .map_err(|err_msg| anyhow!("序列化`ComicInfo.xml`失败: {err_msg}"))?;
+
+        let zip_file = std::fs::File::create(&temp_archive_path)
+            .context(format!("创建文件`{}`失败", temp_archive_path.display()))?;
+        let mut zip_writer = ZipWriter::new(zip_file);
+        zip_writer
+            .start_file("ComicInfo.xml", SimpleFileOptions::default())
+            .context("创建`ComicInfo.xml`失败")?;
+        zip_writer
+            .write_all(comic_info_xml.as_bytes())
+            .context("写入`ComicInfo.xml`失败")?;
+
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(chapter_download_dir)
+            .context(format!("读取目录`{}`失败", chapter_download_dir.display()))?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_img() || path.ends_with("章节元数据.json"))
+            .collect();
+        paths.sort();
+        // 图片本身已经是压缩过的，再压缩一遍只会浪费CPU，所以直接存储
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        for path in paths {
+            let Some(filename) = path.file_name().map(|name| name.to_string_lossy()) else {
+                continue;
+            };
+            let options = if path.is_img() {
+                stored
+            } else {
+                SimpleFileOptions::default()
+            };
+            zip_writer
+                .start_file(filename.as_ref(), options)
+                .context(format!("在cbz中创建`{filename}`失败"))?;
+            let mut file =
+                std::fs::File::open(&path).context(format!("打开`{}`失败", path.display()))?;
+            std::io::copy(&mut file, &mut zip_writer)
+                .context(format!("将`{}`写入cbz失败", path.display()))?;
+        }
+        zip_writer.finish().context("关闭cbz失败")?;
+
+        std::fs::rename(&temp_archive_path, &archive_path).context(format!(
+            "将`{}`重命名为`{}`失败",
+            temp_archive_path.display(),
+            archive_path.display()
+        ))?;
+        std::fs::remove_dir_all(chapter_download_dir)
+            .context(format!("删除目录`{}`失败", chapter_download_dir.display()))?;
+
+        Ok(())
+    }
+
+    /// cbz中是否有AVIF图片
+    pub fn archive_has_avif(archive_path: &Path) -> anyhow::Result<bool> {
+        let zip_file = std::fs::File::open(archive_path)
+            .context(format!("打开`{}`失败", archive_path.display()))?;
+        let zip_archive =
+            ZipArchive::new(zip_file).context(format!("读取`{}`失败", archive_path.display()))?;
+        let has_avif = zip_archive
+            .file_names()
+            .any(|name| Path::new(name).is_avif());
+        Ok(has_avif)
+    }
+
+    /// 读取cbz中的`章节元数据.json`
+    pub fn read_metadata_from_archive(archive_path: &Path) -> anyhow::Result<serde_json::Value> {
+        let zip_file = std::fs::File::open(archive_path)
+            .context(format!("打开`{}`失败", archive_path.display()))?;
+        let mut zip_archive =
+            ZipArchive::new(zip_file).context(format!("读取`{}`失败", archive_path.display()))?;
+        let mut metadata_file = zip_archive.by_name("章节元数据.json").context(format!(
+            "`{}`中没有`章节元数据.json`",
+            archive_path.display()
+        ))?;
+        let mut metadata_str = String::new();
+        metadata_file
+            .read_to_string(&mut metadata_str)
+            .context(format!(
+                "读取`{}`中的`章节元数据.json`失败",
+                archive_path.display()
+            ))?;
+
+        let chapter_json = serde_json::from_str(&metadata_str).context(format!(
+            "将`{}`中的`章节元数据.json`反序列化为serde_json::Value失败",
+            archive_path.display()
+        ))?;
+
+        Ok(chapter_json)
+    }
 }
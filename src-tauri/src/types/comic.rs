@@ -1,5 +1,5 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap},
     path::{Path, PathBuf},
 };
 
@@ -10,7 +10,8 @@ use tauri::AppHandle;
 use walkdir::WalkDir;
 
 use crate::{
-    extensions::{AppHandleExt, WalkDirEntryExt},
+    extensions::{AnyhowErrorToStringChain, PathIsImg, WalkDirEntryExt},
+    migrations::{self, WithSchemaVersion},
     responses::{GetComicRespData, RelatedListRespData},
     utils,
 };
@@ -44,10 +45,16 @@ pub struct Comic {
     pub is_favorite: bool,
     #[serde(rename = "is_aids")]
     pub is_aids: bool,
+    /// 下载时所在的收藏夹名，用于`dir_fmt`中的`{favorite_folder}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub favorite_folder: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_downloaded: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub comic_download_dir: Option<PathBuf>,
+    /// 已下载的图片中是否有AVIF，导出PDF时无法解码AVIF
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_avif_images: Option<bool>,
 }
 
 impl Comic {
@@ -71,6 +78,9 @@ impl Comic {
                     order,
                     is_downloaded: None,
                     chapter_download_dir: None,
+                    block_num_overrides: BTreeMap::new(),
+                    gif_page_exts: BTreeMap::new(),
+                    images_deleted: false,
                 };
                 Some(chapter_info)
             })
@@ -83,6 +93,9 @@ impl Comic {
                 order: 1,
                 is_downloaded: None,
                 chapter_download_dir: None,
+                block_num_overrides: BTreeMap::new(),
+                gif_page_exts: BTreeMap::new(),
+                images_deleted: false,
             });
         }
 
@@ -104,18 +117,26 @@ impl Comic {
             liked: comic.liked,
             is_favorite: comic.is_favorite,
             is_aids: comic.is_aids,
+            favorite_folder: None,
             is_downloaded: None,
             comic_download_dir: None,
+            has_avif_images: None,
         };
 
         let id_to_dir_map =
             utils::create_id_to_dir_map(app).context("创建漫画ID到下载目录映射失败")?;
 
-        // TODO: 这是为了兼容v0.15.4及之前的版本，后续需要移除，计划在v0.17.0之后移除
+        // 已下载的漫画的元数据可能是旧版本的，先迁移到最新版本
         if let Some(comic_download_dir) = id_to_dir_map.get(&comic.id) {
-            comic
-                .create_chapter_metadata_for_old_version(comic_download_dir)
-                .context("为旧版本创建章节元数据失败")?;
+            let metadata_path = comic_download_dir.join("元数据.json");
+            let comic_json = migrations::read_comic_metadata(&metadata_path)
+                .context(format!("读取并迁移`{}`失败", metadata_path.display()))?;
+            // 沿用下载时所在的收藏夹，否则之后创建的下载任务会按`dir_fmt`算出不同的目录
+            let local_comic: Comic = serde_json::from_value(comic_json).context(format!(
+                "将`{}`反序列化为Comic失败",
+                metadata_path.display()
+            ))?;
+            comic.favorite_folder = local_comic.favorite_folder;
         }
 
         comic
@@ -138,9 +159,9 @@ impl Comic {
     }
 
     pub fn from_metadata(metadata_path: &Path) -> anyhow::Result<Comic> {
-        let comic_json = std::fs::read_to_string(metadata_path)
-            .context(format!("读取`{}`失败", metadata_path.display()))?;
-        let mut comic = serde_json::from_str::<Comic>(&comic_json).context(format!(
+        let comic_json = migrations::read_comic_metadata(metadata_path)
+            .context(format!("读取并迁移`{}`失败", metadata_path.display()))?;
+        let mut comic: Comic = serde_json::from_value(comic_json).context(format!(
             "将`{}`反序列化为Comic失败",
             metadata_path.display()
         ))?;
@@ -150,11 +171,6 @@ impl Comic {
             .context(format!("`{}`没有父目录", metadata_path.display()))?;
         let comic_download_dir = parent.to_path_buf();
 
-        // TODO: 这是为了兼容v0.15.4及之前的版本，后续需要移除，计划在v0.17.0之后移除
-        comic
-            .create_chapter_metadata_for_old_version(&comic_download_dir)
-            .context("为旧版本创建章节元数据失败")?;
-
         comic.comic_download_dir = Some(comic_download_dir);
         comic.is_downloaded = Some(true);
 
@@ -165,58 +181,20 @@ impl Comic {
         Ok(comic)
     }
 
-    pub fn get_comic_download_dir_name(&self) -> anyhow::Result<String> {
-        let comic_download_dir = self
-            .comic_download_dir
-            .as_ref()
-            .context("`comic_download_dir`字段为`None`")?;
-
-        let comic_download_dir_name = comic_download_dir
-            .file_name()
-            .context(format!(
-                "获取`{}`的目录名失败",
-                comic_download_dir.display()
-            ))?
-            .to_string_lossy()
-            .to_string();
-
-        Ok(comic_download_dir_name)
-    }
-
-    pub fn get_comic_export_dir(&self, app: &AppHandle) -> anyhow::Result<PathBuf> {
-        let (download_dir, export_dir) = {
-            let config = app.get_config();
-            let config = config.read();
-            (config.download_dir.clone(), config.export_dir.clone())
-        };
-
-        let Some(comic_download_dir) = self.comic_download_dir.clone() else {
-            return Err(anyhow!("`comic_download_dir`字段为`None`"));
-        };
-
-        let relative_dir = comic_download_dir
-            .strip_prefix(&download_dir)
-            .context(format!(
-                "无法从路径`{}`中移除前缀`{}`",
-                comic_download_dir.display(),
-                download_dir.display()
-            ))?;
-
-        let comic_export_dir = export_dir.join(relative_dir);
-        Ok(comic_export_dir)
-    }
-
     pub fn save_comic_metadata(&self) -> anyhow::Result<()> {
         let mut comic = self.clone();
         // 将漫画的is_downloaded和comic_download_dir字段设置为None
         // 这样能使这些字段在序列化时被忽略
         comic.is_downloaded = None;
         comic.comic_download_dir = None;
+        comic.has_avif_images = None;
         for chapter in &mut comic.chapter_infos {
             // 将章节的is_downloaded和chapter_download_dir字段设置为None
             // 这样能使这些字段在序列化时被忽略
             chapter.is_downloaded = None;
             chapter.chapter_download_dir = None;
+            // 图片是否已删除以章节元数据为准
+            chapter.images_deleted = false;
         }
 
         let comic_download_dir = self
@@ -228,9 +206,13 @@ impl Comic {
         std::fs::create_dir_all(comic_download_dir)
             .context(format!("创建目录`{}`失败", comic_download_dir.display()))?;
 
-        let comic_json = serde_json::to_string_pretty(&comic).context("将Comic序列化为json失败")?;
+        let comic_json = serde_json::to_string_pretty(&WithSchemaVersion {
+            schema_version: migrations::COMIC_SCHEMA_VERSION,
+            metadata: &comic,
+        })
+        .context("将Comic序列化为json失败")?;
 
-        std::fs::write(&metadata_path, comic_json)
+        utils::write_metadata(&metadata_path, &comic_json)
             .context(format!("写入文件`{}`失败", metadata_path.display()))?;
 
         Ok(())
@@ -256,24 +238,64 @@ impl Comic {
             return Ok(());
         }
 
+        let mut has_avif_images = false;
         for entry in WalkDir::new(comic_download_dir)
             .into_iter()
             .filter_map(Result::ok)
         {
-            if !entry.is_chapter_metadata() {
+            let metadata_path = entry.path();
+            if entry.file_type().is_file() && metadata_path.is_avif() {
+                has_avif_images = true;
                 continue;
             }
+            // 章节元数据所在的目录是章节目录，打包为cbz的章节，去掉`.cbz`后缀就是章节目录
+            let (chapter_json, chapter_download_dir) = if entry.is_chapter_metadata() {
+                let parent = metadata_path
+                    .parent()
+                    .context(format!("`{}`没有父目录", metadata_path.display()))?;
 
-            let metadata_path = entry.path();
+                // 旧版本的章节元数据先迁移到最新版本
+                if let Err(err) = migrations::migrate_chapter_metadata(metadata_path, false) {
+                    let err_title = format!("`{}`迁移章节元数据失败", self.name);
+                    let string_chain = err.to_string_chain();
+                    tracing::error!(err_title, message = string_chain);
+                }
+
+                let chapter_json = match utils::read_metadata::<serde_json::Value>(metadata_path) {
+                    Ok(chapter_json) => chapter_json,
+                    // 损坏且没有备份的章节元数据，根据章节目录名从漫画元数据中重建
+                    Err(err) => match self.rebuild_chapter_metadata(parent) {
+                        Ok(chapter_json) => chapter_json,
+                        Err(rebuild_err) => {
+                            let err_title = format!(
+                                "`{}`的章节元数据`{}`已损坏，且无法重建，已跳过",
+                                self.name,
+                                metadata_path.display()
+                            );
+                            let string_chain = err
+                                .context(format!("重建失败: {rebuild_err:#}"))
+                                .to_string_chain();
+                            tracing::error!(err_title, message = string_chain);
+                            continue;
+                        }
+                    },
+                };
 
-            let metadata_str = std::fs::read_to_string(metadata_path)
-                .context(format!("读取`{}`失败", metadata_path.display()))?;
+                (chapter_json, parent.to_path_buf())
+            } else if entry.is_chapter_archive() {
+                // 不是由本程序打包的cbz没有章节元数据，直接跳过
+                let Ok(chapter_json) = ChapterInfo::read_metadata_from_archive(metadata_path)
+                else {
+                    continue;
+                };
+                if ChapterInfo::archive_has_avif(metadata_path).unwrap_or(false) {
+                    has_avif_images = true;
+                }
 
-            let chapter_json: serde_json::Value =
-                serde_json::from_str(&metadata_str).context(format!(
-                    "将`{}`反序列化为serde_json::Value失败",
-                    metadata_path.display()
-                ))?;
+                (chapter_json, metadata_path.with_extension(""))
+            } else {
+                continue;
+            };
 
             let chapter_id = chapter_json
                 .get("chapterId")
@@ -285,44 +307,49 @@ impl Comic {
                 .iter_mut()
                 .find(|chapter| chapter.chapter_id == chapter_id)
             {
-                let parent = metadata_path
-                    .parent()
-                    .context(format!("`{}`没有父目录", metadata_path.display()))?;
-                chapter_info.chapter_download_dir = Some(parent.to_path_buf());
+                chapter_info.chapter_download_dir = Some(chapter_download_dir);
                 chapter_info.is_downloaded = Some(true);
+                chapter_info.images_deleted = chapter_json
+                    .get("imagesDeleted")
+                    .and_then(serde_json::Value::as_bool)
+                    .unwrap_or(false);
             }
         }
+        self.has_avif_images = Some(has_avif_images);
         Ok(())
     }
 
-    fn create_chapter_metadata_for_old_version(
+    /// 根据章节目录名找到对应的章节信息，重新保存章节元数据并返回它的json
+    ///
+    /// 只有章节目录名就是章节标题时(例如`dir_fmt`的最后一层是`{chapter_title}`)才能找到
+    fn rebuild_chapter_metadata(
         &self,
-        comic_download_dir: &Path,
-    ) -> anyhow::Result<()> {
-        let mut chapter_dirs = HashSet::new();
-        for entry in std::fs::read_dir(comic_download_dir)?.filter_map(Result::ok) {
-            let Ok(file_type) = entry.file_type() else {
-                continue;
-            };
-            if !file_type.is_dir() {
-                continue;
-            }
-            chapter_dirs.insert(entry.path());
-        }
-
-        for chapter_info in &self.chapter_infos {
-            let old_chapter_dir = comic_download_dir.join(&chapter_info.chapter_title);
-            let old_chapter_dir_exists = chapter_dirs.contains(&old_chapter_dir);
-            let old_chapter_metadata_exists = old_chapter_dir.join("章节元数据.json").exists();
-            if old_chapter_dir_exists && !old_chapter_metadata_exists {
-                // 如果旧版本的章节目录存在，但没有元数据文件，就创建一个
-                let mut info = chapter_info.clone();
-                info.chapter_download_dir = Some(old_chapter_dir);
-                info.is_downloaded = Some(true);
-                info.save_chapter_metadata()?;
-            }
-        }
-
-        Ok(())
+        chapter_download_dir: &Path,
+    ) -> anyhow::Result<serde_json::Value> {
+        let dir_name = chapter_download_dir
+            .file_name()
+            .context(format!(
+                "获取`{}`的目录名失败",
+                chapter_download_dir.display()
+            ))?
+            .to_string_lossy();
+
+        let mut chapter_info = self
+            .chapter_infos
+            .iter()
+            .find(|chapter| utils::filename_filter(&chapter.chapter_title) == dir_name)
+            .cloned()
+            .context(format!("漫画元数据中没有与目录名`{dir_name}`对应的章节"))?;
+        chapter_info.chapter_download_dir = Some(chapter_download_dir.to_path_buf());
+        chapter_info.save_chapter_metadata()?;
+
+        tracing::warn!(
+            "根据目录名重建了章节元数据`{}`",
+            chapter_download_dir.join("章节元数据.json").display()
+        );
+
+        let chapter_json =
+            serde_json::to_value(&chapter_info).context("将ChapterInfo序列化为json失败")?;
+        Ok(chapter_json)
     }
 }
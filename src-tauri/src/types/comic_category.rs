@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// 漫画的主分类
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub enum ComicCategory {
+    All,
+    Doujin,
+    Single,
+    Short,
+    Another,
+    Hanman,
+    Meiman,
+    Cosplay,
+    ThreeD,
+    EnglishSite,
+}
+
+impl ComicCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ComicCategory::All => "0",
+            ComicCategory::Doujin => "doujin",
+            ComicCategory::Single => "single",
+            ComicCategory::Short => "short",
+            ComicCategory::Another => "another",
+            ComicCategory::Hanman => "hanman",
+            ComicCategory::Meiman => "meiman",
+            ComicCategory::Cosplay => "doujin_cosplay",
+            ComicCategory::ThreeD => "3D",
+            ComicCategory::EnglishSite => "english_site",
+        }
+    }
+}
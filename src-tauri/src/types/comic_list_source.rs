@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+use super::{ComicCategory, SearchMainTag, SearchSort, SearchTime};
+
+/// 批量下载的漫画列表来源
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub enum ComicListSource {
+    /// 搜索结果的所有页
+    #[serde(rename_all = "camelCase")]
+    Search {
+        keyword: String,
+        sort: SearchSort,
+        main_tag: SearchMainTag,
+        time: SearchTime,
+        category: ComicCategory,
+    },
+    /// 每周必看的某一期的某个分类
+    #[serde(rename_all = "camelCase")]
+    Weekly {
+        category_id: String,
+        type_id: String,
+    },
+}
+
+impl ComicListSource {
+    pub fn name(&self) -> String {
+        match self {
+            ComicListSource::Search { keyword, .. } => format!("搜索`{keyword}`的结果"),
+            ComicListSource::Weekly { .. } => "每周必看".to_string(),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use image::ImageFormat;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
@@ -7,14 +8,31 @@ pub enum DownloadFormat {
     Jpeg,
     Png,
     Webp,
+    Avif,
+    /// 保持原图的格式，无需拼接的图片直接保存原始数据
+    Original,
 }
 
 impl DownloadFormat {
-    pub fn extension(self) -> &'static str {
+    /// 保存时使用的扩展名，`Original`要等下载完成后才知道原图的格式，所以返回`None`
+    pub fn extension(self) -> Option<&'static str> {
         match self {
-            DownloadFormat::Jpeg => "jpg",
-            DownloadFormat::Png => "png",
-            DownloadFormat::Webp => "webp",
+            DownloadFormat::Jpeg => Some("jpg"),
+            DownloadFormat::Png => Some("png"),
+            DownloadFormat::Webp => Some("webp"),
+            DownloadFormat::Avif => Some("avif"),
+            DownloadFormat::Original => None,
+        }
+    }
+
+    /// 原图格式为`src_format`时，保存使用的图片格式
+    pub fn image_format(self, src_format: ImageFormat) -> ImageFormat {
+        match self {
+            DownloadFormat::Jpeg => ImageFormat::Jpeg,
+            DownloadFormat::Png => ImageFormat::Png,
+            DownloadFormat::Webp => ImageFormat::WebP,
+            DownloadFormat::Avif => ImageFormat::Avif,
+            DownloadFormat::Original => src_format,
         }
     }
 }
@@ -0,0 +1,38 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::AppHandle;
+
+use crate::{responses::GetCategoryFilterRespData, utils};
+
+use super::ComicInSearch;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GetCategoryFilterResult {
+    pub total: i64,
+    pub content: Vec<ComicInSearch>,
+}
+
+impl GetCategoryFilterResult {
+    pub fn from_resp_data(
+        app: &AppHandle,
+        resp_data: GetCategoryFilterRespData,
+    ) -> anyhow::Result<GetCategoryFilterResult> {
+        let id_to_dir_map =
+            utils::create_id_to_dir_map(app).context("创建漫画ID到下载目录映射失败")?;
+
+        let content = resp_data
+            .content
+            .into_iter()
+            .map(|comic| ComicInSearch::from_resp_data(comic, &id_to_dir_map))
+            .collect::<anyhow::Result<_>>()?;
+
+        let get_category_filter_result = GetCategoryFilterResult {
+            total: resp_data.total,
+            content,
+        };
+
+        Ok(get_category_filter_result)
+    }
+}
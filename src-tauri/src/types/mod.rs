@@ -1,26 +1,44 @@
 mod chapter_info;
 mod comic;
+mod comic_category;
 mod comic_info;
+mod comic_list_source;
 mod download_format;
 mod favorite_sort;
+mod get_category_filter_result;
 mod get_favorite_result;
 mod get_weekly_result;
 mod log_level;
+mod pdf_image_encoding;
+mod pdf_page_size;
+mod png_compression;
 mod proxy_mode;
+mod search_main_tag;
 mod search_result;
 mod search_sort;
+mod search_time;
+mod storage_mode;
 
 pub use chapter_info::*;
 pub use comic::*;
+pub use comic_category::*;
 pub use comic_info::*;
+pub use comic_list_source::*;
 pub use download_format::*;
 pub use favorite_sort::*;
+pub use get_category_filter_result::*;
 pub use get_favorite_result::*;
 pub use get_weekly_result::*;
 pub use log_level::*;
+pub use pdf_image_encoding::*;
+pub use pdf_page_size::*;
+pub use png_compression::*;
 pub use proxy_mode::*;
+pub use search_main_tag::*;
 pub use search_result::*;
 pub use search_sort::*;
+pub use search_time::*;
+pub use storage_mode::*;
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
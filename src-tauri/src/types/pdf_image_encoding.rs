@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// 导出PDF时，非JPEG图片的编码方式
+///
+/// JPEG图片总是原样嵌入PDF，不受这个选项影响
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub enum PdfImageEncoding {
+    /// 转码为JPEG，有损，但PDF体积小
+    #[default]
+    Jpeg,
+    /// 解码为原始像素后用Flate压缩，无损，但PDF体积大
+    Flate,
+}
@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// 1毫米对应的PDF单位(pt)
+const PT_PER_MM: f32 = 72.0 / 25.4;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Type)]
+pub enum PdfPageSize {
+    /// 页面尺寸与图片的像素尺寸一致
+    #[default]
+    Original,
+    A4,
+    A5,
+    Letter,
+    Custom,
+}
+
+impl PdfPageSize {
+    /// 返回页面的宽和高(单位为pt)，`Original`返回`None`
+    ///
+    /// `custom_width_mm`和`custom_height_mm`只在`Custom`时使用
+    pub fn dimensions(self, custom_width_mm: f32, custom_height_mm: f32) -> Option<(f32, f32)> {
+        match self {
+            PdfPageSize::Original => None,
+            PdfPageSize::A4 => Some((210.0 * PT_PER_MM, 297.0 * PT_PER_MM)),
+            PdfPageSize::A5 => Some((148.0 * PT_PER_MM, 210.0 * PT_PER_MM)),
+            PdfPageSize::Letter => Some((612.0, 792.0)),
+            PdfPageSize::Custom => {
+                Some((custom_width_mm * PT_PER_MM, custom_height_mm * PT_PER_MM))
+            }
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use image::codecs::png::CompressionType;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// 下载格式为PNG时的压缩等级
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub enum PngCompression {
+    /// 编码最快，文件最大
+    Fast,
+    Default,
+    /// 文件最小，编码最慢
+    #[default]
+    Best,
+}
+
+impl From<PngCompression> for CompressionType {
+    fn from(compression: PngCompression) -> Self {
+        match compression {
+            PngCompression::Fast => CompressionType::Fast,
+            PngCompression::Default => CompressionType::Default,
+            PngCompression::Best => CompressionType::Best,
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// 搜索关键词匹配的范围
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub enum SearchMainTag {
+    /// 站内搜索，主要匹配标题
+    Title,
+    Work,
+    Author,
+    Tag,
+    Character,
+}
+
+impl SearchMainTag {
+    pub fn as_i64(&self) -> i64 {
+        match self {
+            SearchMainTag::Title => 0,
+            SearchMainTag::Work => 1,
+            SearchMainTag::Author => 2,
+            SearchMainTag::Tag => 3,
+            SearchMainTag::Character => 4,
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// 只搜索在这个时间范围内更新的漫画
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub enum SearchTime {
+    Today,
+    Week,
+    Month,
+    All,
+}
+
+impl SearchTime {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchTime::Today => "t",
+            SearchTime::Week => "w",
+            SearchTime::Month => "m",
+            SearchTime::All => "a",
+        }
+    }
+}
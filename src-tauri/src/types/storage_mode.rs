@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// 已下载章节的保存方式
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub enum StorageMode {
+    /// 每个章节是一个存放图片的目录
+    #[default]
+    Folder,
+    /// 章节下载完成后打包为cbz，代替章节目录，章节元数据保存在cbz内
+    Cbz,
+}
@@ -1,11 +1,17 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Context;
+use serde::{de::DeserializeOwned, Serialize};
 use tauri::AppHandle;
 use walkdir::WalkDir;
 
 use crate::{
-    extensions::{AppHandleExt, WalkDirEntryExt},
+    extensions::{AnyhowErrorToStringChain, AppHandleExt, WalkDirEntryExt},
     types::Comic,
 };
 
@@ -29,11 +35,170 @@ pub fn filename_filter(s: &str) -> String {
         .to_string()
 }
 
+/// 用`params`的字段格式化`fmt`，返回`fmt`中用`/`分隔的每一层格式化后的名字
+///
+/// 每一层都会经过`filename_filter`处理，格式化后为空的层会被忽略
+pub fn fmt_path_parts<T: Serialize>(fmt: &str, params: &T) -> anyhow::Result<Vec<String>> {
+    use strfmt::strfmt;
+
+    let json_value =
+        serde_json::to_value(params).context("将格式化参数转为serde_json::Value失败")?;
+
+    let json_map = json_value.as_object().context("格式化参数不是JSON对象")?;
+
+    let vars: HashMap<String, String> = json_map
+        .into_iter()
+        .map(|(k, v)| {
+            let key = k.clone();
+            let value = match v {
+                serde_json::Value::String(s) => s.clone(),
+                _ => v.to_string(),
+            };
+            (key, value)
+        })
+        .collect();
+
+    let mut names = Vec::new();
+    for fmt in fmt.split('/') {
+        let name = strfmt(fmt, &vars).context(format!("格式化`{fmt}`失败"))?;
+        let name = filename_filter(&name);
+        if !name.is_empty() {
+            names.push(name);
+        }
+    }
+
+    Ok(names)
+}
+
 // 计算MD5哈希并返回十六进制字符串
 pub fn md5_hex(data: &str) -> String {
     format!("{:x}", md5::compute(data))
 }
 
+/// 在`path`的文件名后追加`.{extension}`，例如`元数据.json`追加`bak`得到`元数据.json.bak`
+pub fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = OsString::from(path.as_os_str());
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// 在`path`的同目录下生成一个不会与其他写入冲突的临时文件路径，例如`元数据.json.<uuid>.tmp`
+fn unique_temp_path(path: &Path) -> PathBuf {
+    let uuid = uuid::Uuid::new_v4();
+    append_extension(path, &format!("{uuid}.tmp"))
+}
+
+/// 将`data`写入`path`并fsync
+fn write_and_sync(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let mut file =
+        std::fs::File::create(path).context(format!("创建文件`{}`失败", path.display()))?;
+    file.write_all(data)
+        .context(format!("写入文件`{}`失败", path.display()))?;
+    file.sync_all()
+        .context(format!("将`{}`同步到磁盘失败", path.display()))?;
+    Ok(())
+}
+
+/// 原子地将元数据`json`写入`path`，避免写入过程中崩溃留下不完整的文件
+///
+/// 先写到同目录下的临时文件并fsync，再重命名覆盖`path`。
+/// 覆盖前如果`path`是合法的json，则先复制一份为`.bak`，供`read_metadata`恢复使用
+///
+/// 多个章节下载任务可能同时写同一个`元数据.json`，所以每次写入都用不同的临时文件，
+/// `.bak`也先复制到临时文件再重命名，这样并发写入时每次重命名都是完整的文件
+pub fn write_metadata(path: &Path, json: &str) -> anyhow::Result<()> {
+    let temp_path = unique_temp_path(path);
+    let backup_path = append_extension(path, "bak");
+
+    let result = write_and_sync(&temp_path, json.as_bytes());
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result?;
+
+    let old_json_is_valid = std::fs::read_to_string(path)
+        .ok()
+        .is_some_and(|old_json| serde_json::from_str::<serde_json::Value>(&old_json).is_ok());
+    if old_json_is_valid {
+        let temp_backup_path = unique_temp_path(&backup_path);
+        let result = std::fs::copy(path, &temp_backup_path)
+            .context(format!(
+                "将`{}`复制为`{}`失败",
+                path.display(),
+                temp_backup_path.display()
+            ))
+            .and_then(|_| {
+                std::fs::rename(&temp_backup_path, &backup_path).context(format!(
+                    "将`{}`重命名为`{}`失败",
+                    temp_backup_path.display(),
+                    backup_path.display()
+                ))
+            });
+        if let Err(err) = result {
+            let _ = std::fs::remove_file(&temp_backup_path);
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(err.context(format!("备份`{}`失败", path.display())));
+        }
+    }
+
+    if let Err(err) = std::fs::rename(&temp_path, path) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(anyhow::Error::from(err).context(format!(
+            "将`{}`重命名为`{}`失败",
+            temp_path.display(),
+            path.display()
+        )));
+    }
+    // 重命名本身也要落盘，否则崩溃后目录项可能还指向旧文件
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        std::fs::File::open(parent)
+            .and_then(|dir| dir.sync_all())
+            .context(format!("将目录`{}`同步到磁盘失败", parent.display()))?;
+    }
+
+    Ok(())
+}
+
+/// 读取元数据`path`并反序列化为`T`
+///
+/// 如果`path`损坏(例如旧版本写入时崩溃导致内容不完整)，则尝试用`.bak`备份恢复
+pub fn read_metadata<T: DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let err = match read_json(path) {
+        Ok(metadata) => return Ok(metadata),
+        Err(err) => err,
+    };
+
+    let backup_path = append_extension(path, "bak");
+    let backup = std::fs::read_to_string(&backup_path)
+        .ok()
+        .and_then(|backup_json| Some((serde_json::from_str::<T>(&backup_json).ok()?, backup_json)));
+    let Some((metadata, backup_json)) = backup else {
+        return Err(err.context(format!(
+            "`{}`已损坏，且没有可用的备份`{}`",
+            path.display(),
+            backup_path.display()
+        )));
+    };
+
+    tracing::warn!(
+        "`{}`已损坏，使用备份`{}`恢复",
+        path.display(),
+        backup_path.display()
+    );
+    write_metadata(path, &backup_json).context(format!("用备份恢复`{}`失败", path.display()))?;
+
+    Ok(metadata)
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let json = std::fs::read_to_string(path).context(format!("读取`{}`失败", path.display()))?;
+    let metadata =
+        serde_json::from_str(&json).context(format!("反序列化`{}`失败", path.display()))?;
+    Ok(metadata)
+}
+
 pub fn create_id_to_dir_map(app: &AppHandle) -> anyhow::Result<HashMap<i64, PathBuf>> {
     let mut id_to_dir_map: HashMap<i64, PathBuf> = HashMap::new();
     let download_dir = app.get_config().read().download_dir.clone();
@@ -50,11 +215,16 @@ pub fn create_id_to_dir_map(app: &AppHandle) -> anyhow::Result<HashMap<i64, Path
             continue;
         }
 
-        let metadata_str =
-            std::fs::read_to_string(path).context(format!("读取`{}`失败", path.display()))?;
-        let comic_json: serde_json::Value = serde_json::from_str(&metadata_str).context(
-            format!("将`{}`反序列化为serde_json::Value失败", path.display()),
-        )?;
+        // 损坏且无法恢复的元数据只跳过这一个漫画，不影响整个库
+        let comic_json: serde_json::Value = match read_metadata(path) {
+            Ok(comic_json) => comic_json,
+            Err(err) => {
+                let err_title = "创建漫画ID到下载目录映射时遇到损坏的元数据，已跳过";
+                let string_chain = err.to_string_chain();
+                tracing::error!(err_title, message = string_chain);
+                continue;
+            }
+        };
         let id = comic_json
             .get("id")
             .and_then(serde_json::Value::as_i64)
@@ -0,0 +1,120 @@
+//! 检查边生成边写入PDF时的峰值内存
+//!
+//! 计数分配器会替换整个测试二进制的全局分配器，所以放在单独的集成测试中
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+
+use jmcomic_downloader_lib::pdf_writer::PdfWriter;
+use lopdf::{dictionary, Stream};
+
+/// 记录当前线程已分配的字节数和峰值
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    static PEAK: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let _ = ALLOCATED.try_with(|allocated| {
+                let current = allocated.get() + layout.size();
+                allocated.set(current);
+                let _ = PEAK.try_with(|peak| peak.set(peak.get().max(current)));
+            });
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        let _ = ALLOCATED.try_with(|allocated| {
+            allocated.set(allocated.get().saturating_sub(layout.size()));
+        });
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const PAGES_PER_CHAPTER: usize = 20;
+const IMG_WIDTH: u32 = 256;
+const IMG_HEIGHT: u32 = 384;
+
+fn temp_pdf_path() -> PathBuf {
+    std::env::temp_dir().join(format!("pdf-writer-memory-{}.pdf", uuid::Uuid::new_v4()))
+}
+
+/// 生成一张未压缩的灰度图片流，每张图片的内容都不同
+#[allow(clippy::cast_possible_truncation)]
+fn synthetic_image_stream(seed: usize) -> Stream {
+    let content = (0..IMG_WIDTH * IMG_HEIGHT)
+        .map(|i| (i as usize).wrapping_mul(31).wrapping_add(seed) as u8)
+        .collect::<Vec<u8>>();
+    let dict = dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Image",
+        "Width" => IMG_WIDTH,
+        "Height" => IMG_HEIGHT,
+        "ColorSpace" => "DeviceGray",
+        "BitsPerComponent" => 8,
+    };
+    Stream::new(dict, content)
+}
+
+/// 边生成图片边写入`chapter_count`个章节，返回写入期间相对于开始时的峰值内存
+fn write_synthetic_pdf(save_path: &Path, chapter_count: usize) -> usize {
+    let baseline = ALLOCATED.with(Cell::get);
+    PEAK.with(|peak| peak.set(baseline));
+
+    let mut writer = PdfWriter::create(save_path).unwrap();
+    for chapter in 0..chapter_count {
+        let first_page_index = writer.page_count();
+        let mut first_page_id = None;
+        for page in 0..PAGES_PER_CHAPTER {
+            let image_stream = synthetic_image_stream(chapter * PAGES_PER_CHAPTER + page);
+            let page_size = Some((595.0, 842.0));
+            let page_id = writer
+                .add_image_page(image_stream, (IMG_WIDTH, IMG_HEIGHT), page_size)
+                .unwrap();
+            first_page_id.get_or_insert(page_id);
+        }
+        writer.add_bookmark(format!("第{}话", chapter + 1), first_page_id.unwrap());
+        writer.add_page_label(first_page_index, dictionary! { "S" => "D" });
+    }
+    writer.finish(dictionary! {}).unwrap();
+
+    PEAK.with(Cell::get) - baseline
+}
+
+/// 章节数增加到原来的8倍，峰值内存只增加偏移量和页面ID之类的少量记录，与图片数据总量无关
+#[test]
+fn peak_memory_does_not_grow_with_chapter_count() {
+    let image_size = (IMG_WIDTH * IMG_HEIGHT) as usize;
+
+    let small_path = temp_pdf_path();
+    let small_peak = write_synthetic_pdf(&small_path, 5);
+    let large_path = temp_pdf_path();
+    let large_peak = write_synthetic_pdf(&large_path, 40);
+    let large_file_size = std::fs::metadata(&large_path).unwrap().len();
+
+    // 峰值内存不超过几张图片的大小
+    assert!(large_peak < image_size * 4, "峰值内存{large_peak}过大");
+    // 章节数增加后，峰值内存的增量小于一张图片
+    assert!(
+        large_peak < small_peak + image_size,
+        "峰值内存随章节数增长: {small_peak} -> {large_peak}"
+    );
+    // 图片数据总量远大于峰值内存
+    assert!(
+        usize::try_from(large_file_size).unwrap() > large_peak * 50,
+        "文件大小{large_file_size}与峰值内存{large_peak}相比太小"
+    );
+
+    let _ = std::fs::remove_file(small_path);
+    let _ = std::fs::remove_file(large_path);
+}
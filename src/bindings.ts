@@ -27,9 +27,9 @@ async login(username: string, password: string) : Promise<Result<GetUserProfileR
     else return { status: "error", error: e  as any };
 }
 },
-async search(keyword: string, page: number, sort: SearchSort) : Promise<Result<SearchResultVariant, CommandError>> {
+async search(keyword: string, page: number, sort: SearchSort, mainTag: SearchMainTag, time: SearchTime, category: ComicCategory) : Promise<Result<SearchResultVariant, CommandError>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("search", { keyword, page, sort }) };
+    return { status: "ok", data: await TAURI_INVOKE("search", { keyword, page, sort, mainTag, time, category }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -67,6 +67,30 @@ async getWeekly(categoryId: string, typeId: string) : Promise<Result<GetWeeklyRe
     else return { status: "error", error: e  as any };
 }
 },
+async getCategories() : Promise<Result<GetCategoriesRespData, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_categories") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getCategoryFilter(categorySlug: string, page: number, sort: SearchSort, time: SearchTime) : Promise<Result<GetCategoryFilterResult, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_category_filter", { categorySlug, page, sort, time }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getLatest(page: number) : Promise<Result<ComicInSearch[], CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_latest", { page }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async getUserProfile() : Promise<Result<GetUserProfileRespData, CommandError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("get_user_profile") };
@@ -115,9 +139,17 @@ async downloadComic(aid: number) : Promise<Result<null, CommandError>> {
     else return { status: "error", error: e  as any };
 }
 },
-async downloadAllFavorites() : Promise<Result<null, CommandError>> {
+async downloadAllFavorites(folderIds: number[]) : Promise<Result<null, CommandError>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("download_all_favorites") };
+    return { status: "ok", data: await TAURI_INVOKE("download_all_favorites", { folderIds }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async downloadComicList(source: ComicListSource, maxItems: number) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("download_comic_list", { source, maxItems }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -131,6 +163,33 @@ async updateDownloadedComics() : Promise<Result<null, CommandError>> {
     else return { status: "error", error: e  as any };
 }
 },
+async refreshLibraryMetadata(moveDirs: boolean) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("refresh_library_metadata", { moveDirs }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async checkLibraryUpdates() : Promise<Result<LibraryUpdateReport, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("check_library_updates") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getLibraryUpdateReport() : Promise<LibraryUpdateReport | null> {
+    return await TAURI_INVOKE("get_library_update_report");
+},
+async cancelBatchJob(kind: BatchJobKind) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("cancel_batch_job", { kind }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async showPathInFileManager(path: string) : Promise<Result<null, CommandError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("show_path_in_file_manager", { path }) };
@@ -147,25 +206,90 @@ async syncFavoriteFolder() : Promise<Result<null, CommandError>> {
     else return { status: "error", error: e  as any };
 }
 },
+async createFavoriteFolder(folderName: string) : Promise<Result<EditFavoriteFolderRespData, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("create_favorite_folder", { folderName }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async renameFavoriteFolder(folderId: number, folderName: string) : Promise<Result<EditFavoriteFolderRespData, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("rename_favorite_folder", { folderId, folderName }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async deleteFavoriteFolder(folderId: number) : Promise<Result<EditFavoriteFolderRespData, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("delete_favorite_folder", { folderId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async moveFavoriteComic(aid: number, folderId: number) : Promise<Result<EditFavoriteFolderRespData, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("move_favorite_comic", { aid, folderId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async addFavoriteComic(aid: number, folderId: number | null) : Promise<Result<boolean, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("add_favorite_comic", { aid, folderId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async removeFavoriteComic(aid: number) : Promise<Result<boolean, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("remove_favorite_comic", { aid }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async getDownloadedComics() : Promise<Comic[]> {
     return await TAURI_INVOKE("get_downloaded_comics");
 },
-async exportCbz(comic: Comic) : Promise<Result<null, CommandError>> {
+async exportCbz(comic: Comic) : Promise<string> {
+    return await TAURI_INVOKE("export_cbz", { comic });
+},
+async exportPdf(comic: Comic) : Promise<string> {
+    return await TAURI_INVOKE("export_pdf", { comic });
+},
+async pauseExportJob(jobId: string) : Promise<Result<null, CommandError>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("export_cbz", { comic }) };
+    return { status: "ok", data: await TAURI_INVOKE("pause_export_job", { jobId }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
 },
-async exportPdf(comic: Comic) : Promise<Result<null, CommandError>> {
+async resumeExportJob(jobId: string) : Promise<Result<null, CommandError>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("export_pdf", { comic }) };
+    return { status: "ok", data: await TAURI_INVOKE("resume_export_job", { jobId }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
 },
+async cancelExportJob(jobId: string) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("cancel_export_job", { jobId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getExportJobs() : Promise<ExportJobSnapshot[]> {
+    return await TAURI_INVOKE("get_export_jobs");
+},
 async getLogsDirSize() : Promise<Result<number, CommandError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("get_logs_dir_size") };
@@ -205,6 +329,9 @@ async getSyncedComicInWeekly(comic: ComicInWeekly) : Promise<Result<ComicInWeekl
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
+},
+async migrateLibrary(dryRun: boolean) : Promise<LibraryMigrationReport> {
+    return await TAURI_INVOKE("migrate_library", { dryRun });
 }
 }
 
@@ -212,22 +339,30 @@ async getSyncedComicInWeekly(comic: ComicInWeekly) : Promise<Result<ComicInWeekl
 
 
 export const events = __makeEvents__<{
+checkLibraryUpdatesEvent: CheckLibraryUpdatesEvent,
+diskSpaceEvent: DiskSpaceEvent,
 downloadAllFavoritesEvent: DownloadAllFavoritesEvent,
+downloadComicListEvent: DownloadComicListEvent,
 downloadSleepingEvent: DownloadSleepingEvent,
 downloadSpeedEvent: DownloadSpeedEvent,
 downloadTaskEvent: DownloadTaskEvent,
 exportCbzEvent: ExportCbzEvent,
 exportPdfEvent: ExportPdfEvent,
 logEvent: LogEvent,
+refreshLibraryMetadataEvent: RefreshLibraryMetadataEvent,
 updateDownloadedComicsEvent: UpdateDownloadedComicsEvent
 }>({
+checkLibraryUpdatesEvent: "check-library-updates-event",
+diskSpaceEvent: "disk-space-event",
 downloadAllFavoritesEvent: "download-all-favorites-event",
+downloadComicListEvent: "download-comic-list-event",
 downloadSleepingEvent: "download-sleeping-event",
 downloadSpeedEvent: "download-speed-event",
 downloadTaskEvent: "download-task-event",
 exportCbzEvent: "export-cbz-event",
 exportPdfEvent: "export-pdf-event",
 logEvent: "log-event",
+refreshLibraryMetadataEvent: "refresh-library-metadata-event",
 updateDownloadedComicsEvent: "update-downloaded-comics-event"
 })
 
@@ -238,41 +373,68 @@ updateDownloadedComicsEvent: "update-downloaded-comics-event"
 /** user-defined types **/
 
 export type ApiDomainMode = "Domain1" | "Domain2" | "Domain3" | "Domain4" | "Domain5" | "Custom"
+export type BatchJobKind = "DownloadAllFavorites" | "UpdateDownloadedComics" | "RefreshLibraryMetadata" | "CheckLibraryUpdates" | "DownloadComicList"
 export type Category = { id: string | null; title: string | null }
+export type CategoryInCategoriesRespData = { id: number; name: string; slug: string; total_albums: number }
 export type CategoryInWeeklyInfo = { id: string; title: string; time: string }
 export type CategoryRespData = { id: string | null; title: string | null }
 export type CategorySub = { id: string | null; title: string | null }
 export type CategorySubRespData = { id: string | null; title: string | null }
-export type ChapterInfo = { chapterId: number; chapterTitle: string; order: number; isDownloaded?: boolean | null; chapterDownloadDir?: string | null }
-export type Comic = { id: number; name: string; addtime: string; description: string; total_views: string; likes: string; chapterInfos: ChapterInfo[]; series_id: string; comment_total: string; author: string[]; tags: string[]; works: string[]; actors: string[]; related_list: RelatedListRespData[]; liked: boolean; is_favorite: boolean; is_aids: boolean; isDownloaded?: boolean | null; comicDownloadDir?: string | null }
+export type ChapterInUpdate = { chapterId: number; chapterTitle: string }
+export type ChapterInfo = { chapterId: number; chapterTitle: string; order: number; isDownloaded?: boolean | null; chapterDownloadDir?: string | null; blockNumOverrides?: { [key in string]: number }; gifPageExts?: { [key in string]: string }; imagesDeleted?: boolean }
+export type CheckLibraryUpdatesEvent = { event: "Start"; data: { total: number } } | { event: "Progress"; data: { comicTitle: string; current: number; total: number } } | { event: "End"; data: { cancelled: boolean; updateCount: number; failedCount: number } }
+export type Comic = { id: number; name: string; addtime: string; description: string; total_views: string; likes: string; chapterInfos: ChapterInfo[]; series_id: string; comment_total: string; author: string[]; tags: string[]; works: string[]; actors: string[]; related_list: RelatedListRespData[]; liked: boolean; is_favorite: boolean; is_aids: boolean; favoriteFolder?: string | null; isDownloaded?: boolean | null; comicDownloadDir?: string | null; hasAvifImages?: boolean | null }
+export type ComicCategory = "All" | "Doujin" | "Single" | "Short" | "Another" | "Hanman" | "Meiman" | "Cosplay" | "ThreeD" | "EnglishSite"
 export type ComicInFavorite = { id: number; author: string; description: string | null; name: string; latestEp: string | null; latestEpAid: string | null; image: string; category: CategoryRespData; categorySub: CategorySubRespData; isDownloaded: boolean; comicDownloadDir: string }
 export type ComicInSearch = { id: number; author: string; name: string; image: string; category: CategoryRespData; categorySub: CategorySubRespData; liked: boolean; isFavorite: boolean; updateAt: number; isDownloaded: boolean; comicDownloadDir: string }
 export type ComicInWeekly = { id: number; author: string; description: string; name: string; image: string; category: Category; category_sub: CategorySub; liked: boolean; is_favorite: boolean; update_at: number; is_downloaded: boolean; comic_download_dir: string }
+export type ComicListSource = { Search: { keyword: string; sort: SearchSort; mainTag: SearchMainTag; time: SearchTime; category: ComicCategory } } | { Weekly: { categoryId: string; typeId: string } }
+export type ComicUpdate = { comic: Comic; localChapterCount: number; remoteChapterCount: number; newChapters: ChapterInUpdate[]; removedChapters: ChapterInUpdate[] }
+export type ComicUpdateFailure = { comicId: number; comicTitle: string; errMessage: string }
 export type CommandError = { err_title: string; err_message: string }
-export type Config = { username: string; password: string; downloadDir: string; exportDir: string; downloadFormat: DownloadFormat; dirFmt: string; proxyMode: ProxyMode; proxyHost: string; proxyPort: number; enableFileLogger: boolean; chapterConcurrency: number; chapterDownloadIntervalSec: number; imgConcurrency: number; imgDownloadIntervalSec: number; downloadAllFavoritesIntervalSec: number; updateDownloadedComicsIntervalSec: number; apiDomainMode: ApiDomainMode; customApiDomain: string; shouldDownloadCover: boolean }
-export type DownloadAllFavoritesEvent = { event: "GetFavoritesStart" } | { event: "GetComicsProgress"; data: { current: number; total: number } } | { event: "StartCreateDownloadTasks"; data: { comicId: number; comicTitle: string; current: number; total: number } } | { event: "CreatingDownloadTask"; data: { comicId: number; current: number } } | { event: "EndCreateDownloadTasks"; data: { comicId: number } } | { event: "GetComicsEnd" }
-export type DownloadFormat = "Jpeg" | "Png" | "Webp"
+export type Config = { username: string; password: string; downloadDir: string; exportDir: string; downloadFormat: DownloadFormat; jpegQuality: number; pngCompression: PngCompression; webpLossless: boolean; webpQuality: number; avifQuality: number; avifSpeed: number; storageMode: StorageMode; dirFmt: string; exportChapterFmt: string; exportComicFmt: string; proxyMode: ProxyMode; proxyHost: string; proxyPort: number; enableFileLogger: boolean; chapterConcurrency: number; chapterDownloadIntervalSec: number; imgConcurrency: number; imgDownloadIntervalSec: number; imgProcessThreadCount: number; imgProcessQueueSize: number; minFreeSpaceMb: number; detectBlockNum: boolean; unscrambleGif: boolean; convertGifToWebp: boolean; downloadAllFavoritesIntervalSec: number; updateDownloadedComicsIntervalSec: number; downloadComicListIntervalSec: number; apiDomainMode: ApiDomainMode; customApiDomain: string; shouldDownloadCover: boolean; exportJobConcurrency: number; exportThreadCount: number; enableAutoExport: boolean; autoExportArchive: ExportArchive; deleteImagesAfterAutoExport: boolean; pdfPageSize: PdfPageSize; pdfCustomPageWidthMm: number; pdfCustomPageHeightMm: number; pdfCoverFirst: boolean; pdfImageEncoding: PdfImageEncoding; pdfJpegQuality: number }
+export type DiskSpaceEvent = { event: "Low"; data: { path: string; availableMb: number; minFreeSpaceMb: number } } | { event: "Recovered" }
+export type DownloadAllFavoritesEvent = { event: "GetFavoritesStart" } | { event: "GetComicsProgress"; data: { current: number; total: number } } | { event: "StartCreateDownloadTasks"; data: { comicId: number; comicTitle: string; current: number; total: number } } | { event: "CreatingDownloadTask"; data: { comicId: number; current: number } } | { event: "EndCreateDownloadTasks"; data: { comicId: number } } | { event: "GetComicsEnd"; data: { cancelled: boolean; processedCount: number; total: number; createdTaskCount: number } }
+export type DownloadComicListEvent = { event: "GetComicListStart" } | { event: "GetComicListProgress"; data: { page: number; foundCount: number } } | { event: "GetComicsProgress"; data: { current: number; total: number } } | { event: "StartCreateDownloadTasks"; data: { comicId: number; comicTitle: string; current: number; total: number } } | { event: "CreatingDownloadTask"; data: { comicId: number; current: number } } | { event: "EndCreateDownloadTasks"; data: { comicId: number } } | { event: "GetComicsEnd"; data: { cancelled: boolean; processedCount: number; total: number; skippedCount: number; createdTaskCount: number } }
+export type DownloadFormat = "Jpeg" | "Png" | "Webp" | "Avif" | "Original"
 export type DownloadSleepingEvent = { id: number; remainingSec: number }
-export type DownloadSpeedEvent = { speed: string }
+export type DownloadSpeedEvent = { speed: string; queuedImgCount: number }
 export type DownloadTaskEvent = { event: "Create"; data: { state: DownloadTaskState; comic: Comic; chapterInfo: ChapterInfo; downloadedImgCount: number; totalImgCount: number } } | { event: "Update"; data: { chapterId: number; state: DownloadTaskState; downloadedImgCount: number; totalImgCount: number } }
 export type DownloadTaskState = "Pending" | "Downloading" | "Paused" | "Cancelled" | "Completed" | "Failed"
+export type EditFavoriteFolderRespData = { status: string; msg: string }
+export type ExportArchive = "Cbz" | "Pdf"
 export type ExportCbzEvent = { event: "Start"; data: { uuid: string; comicTitle: string; total: number } } | { event: "Progress"; data: { uuid: string; current: number } } | { event: "Error"; data: { uuid: string } } | { event: "End"; data: { uuid: string; chapterExportDir: string } }
+export type ExportJobSnapshot = { id: string; comicId: number; comicTitle: string; archive: ExportArchive; state: ExportJobState; current: number; total: number }
+export type ExportJobState = "Pending" | "Exporting" | "Paused" | "Cancelled" | "Completed" | "Failed"
 export type ExportPdfEvent = { event: "CreateStart"; data: { uuid: string; comicTitle: string; total: number } } | { event: "CreateProgress"; data: { uuid: string; current: number } } | { event: "CreateError"; data: { uuid: string } } | { event: "CreateEnd"; data: { uuid: string; chapterExportDir: string } } | { event: "MergeStart"; data: { uuid: string; comicTitle: string } } | { event: "MergeError"; data: { uuid: string } } | { event: "MergeEnd"; data: { uuid: string; chapterExportDir: string } }
 export type FavoriteFolderRespData = { FID: string; UID: string; name: string }
 export type FavoriteSort = "FavoriteTime" | "UpdateTime"
+export type GetCategoriesRespData = { categories: CategoryInCategoriesRespData[] }
+export type GetCategoryFilterResult = { total: number; content: ComicInSearch[] }
 export type GetFavoriteResult = { list: ComicInFavorite[]; folderList: FavoriteFolderRespData[]; total: number; count: number }
 export type GetUserProfileRespData = { uid: string; username: string; email: string; emailverified: string; photo: string; fname: string; gender: string; message: string | null; coin: number; album_favorites: number; s: string; level_name: string; level: number; nextLevelExp: number; exp: string; expPercent: number; album_favorites_max: number; ad_free: boolean; charge: string; jar: string; invitation_qrcode: string; invitation_url: string; invited_cnt: string }
 export type GetWeeklyInfoRespData = { categories: CategoryInWeeklyInfo[]; type: WeeklyType[] }
 export type GetWeeklyResult = { total: number; list: ComicInWeekly[] }
 export type JsonValue = null | boolean | number | string | JsonValue[] | { [key in string]: JsonValue }
+export type LibraryUpdateReport = { checkedAt: number; cancelled: boolean; checkedCount: number; updates: ComicUpdate[]; failed: ComicUpdateFailure[] }
+export type LibraryMigrationReport = { dryRun: boolean; checkedCount: number; migrated: MigrationReport[]; failed: MigrationFailure[] }
 export type LogEvent = { timestamp: string; level: LogLevel; fields: { [key in string]: JsonValue }; target: string; filename: string; line_number: number }
 export type LogLevel = "TRACE" | "DEBUG" | "INFO" | "WARN" | "ERROR"
+export type MigrationFailure = { metadataPath: string; errMessage: string }
+export type MigrationReport = { metadataPath: string; fromVersion: number; toVersion: number; changes: string[] }
+export type PdfImageEncoding = "Jpeg" | "Flate"
+export type PdfPageSize = "Original" | "A4" | "A5" | "Letter" | "Custom"
+export type PngCompression = "Fast" | "Default" | "Best"
 export type ProxyMode = "System" | "NoProxy" | "Custom"
+export type RefreshLibraryMetadataEvent = { event: "Start"; data: { total: number } } | { event: "Progress"; data: { comicTitle: string; current: number; total: number } } | { event: "End"; data: { cancelled: boolean; refreshedCount: number; renamedCount: number; movedCount: number; failedCount: number } }
 export type RelatedListRespData = { id: string; author: string; name: string; image: string }
+export type SearchMainTag = "Title" | "Work" | "Author" | "Tag" | "Character"
 export type SearchResult = { searchQuery: string; total: number; content: ComicInSearch[] }
 export type SearchResultVariant = { SearchResult: SearchResult } | { Comic: Comic }
 export type SearchSort = "Latest" | "View" | "Picture" | "Like"
-export type UpdateDownloadedComicsEvent = { event: "GetComicStart"; data: { total: number } } | { event: "GetComicProgress"; data: { current: number; total: number } } | { event: "CreateDownloadTasksStart"; data: { comicId: number; comicTitle: string; current: number; total: number } } | { event: "CreateDownloadTaskProgress"; data: { comicId: number; current: number } } | { event: "CreateDownloadTasksEnd"; data: { comicId: number } } | { event: "GetComicEnd" }
+export type SearchTime = "Today" | "Week" | "Month" | "All"
+export type StorageMode = "Folder" | "Cbz"
+export type UpdateDownloadedComicsEvent = { event: "GetComicStart"; data: { total: number } } | { event: "GetComicProgress"; data: { current: number; total: number } } | { event: "CreateDownloadTasksStart"; data: { comicId: number; comicTitle: string; current: number; total: number } } | { event: "CreateDownloadTaskProgress"; data: { comicId: number; current: number } } | { event: "CreateDownloadTasksEnd"; data: { comicId: number } } | { event: "GetComicEnd"; data: { cancelled: boolean; processedCount: number; total: number; createdTaskCount: number } }
 export type WeeklyType = { id: string; title: string }
 
 /** tauri-specta globals **/
@@ -1,6 +1,14 @@
 import { defineStore } from 'pinia'
 import { CurrentTabName, ProgressData } from './types.ts'
-import { Comic, Config, GetFavoriteResult, GetUserProfileRespData, GetWeeklyResult, SearchResult } from './bindings.ts'
+import {
+  Comic,
+  Config,
+  GetCategoryFilterResult,
+  GetFavoriteResult,
+  GetUserProfileRespData,
+  GetWeeklyResult,
+  SearchResult,
+} from './bindings.ts'
 import { ref } from 'vue'
 import { ProgressesPaneTabName } from './panes/ProgressesPane/ProgressesPane.vue'
 
@@ -14,6 +22,7 @@ export const useStore = defineStore('store', () => {
   const searchResult = ref<SearchResult>()
   const progressesPaneTabName = ref<ProgressesPaneTabName>('uncompleted')
   const getWeeklyResult = ref<GetWeeklyResult>()
+  const getCategoryFilterResult = ref<GetCategoryFilterResult>()
 
   return {
     config,
@@ -25,5 +34,6 @@ export const useStore = defineStore('store', () => {
     searchResult,
     progressesPaneTabName,
     getWeeklyResult,
+    getCategoryFilterResult,
   }
 })
@@ -1,6 +1,6 @@
 import { DownloadTaskEvent } from './bindings.ts'
 
-export type CurrentTabName = 'search' | 'favorite' | 'weekly' | 'downloaded' | 'chapter'
+export type CurrentTabName = 'search' | 'favorite' | 'weekly' | 'category' | 'downloaded' | 'chapter'
 
 export type ProgressData = Extract<DownloadTaskEvent, { event: 'Create' }>['data'] & {
   percentage: number